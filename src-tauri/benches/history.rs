@@ -0,0 +1,70 @@
+// 板の履歴に1件追加するコスト
+// 従来の実装（BTreeMap を丸ごと複製して Vec::retain で古いものを捨てる）と比べる
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hl_heatmap_lib::book::{BookSide, Level, OrderBookState, DEFAULT_HISTORY_LIMIT_MB};
+use hl_heatmap_lib::history::BucketStat;
+use ordered_float::OrderedFloat;
+
+const LEVELS: usize = 20;
+// (表示する時間幅, 板の更新間隔)（ミリ秒）
+const CASES: [(&str, i64, i64); 2] = [("5m", 300_000, 500), ("1h", 3_600_000, 100)];
+
+fn side(first: f64, step: f64) -> BookSide {
+    (0..LEVELS)
+        .map(|i| (OrderedFloat(first + step * i as f64), Level { size: 1.0 + i as f64, orders: 1 + i as i32 }))
+        .collect()
+}
+
+/// 更新ごとに1レベルだけ数量を変える
+fn tick(buy: &mut BookSide, count: usize) {
+    let price = OrderedFloat(100.0 - (count % LEVELS) as f64 * 0.1);
+    if let Some(level) = buy.get_mut(&price) {
+        level.size += 0.5;
+    }
+}
+
+/// 時間幅いっぱいまで履歴が溜まった状態で1件追加する
+fn update_history(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_history");
+    for (label, window, interval) in CASES {
+        let warm = (window / interval) as usize;
+
+        group.bench_function(BenchmarkId::new("vec_retain", label), |b| {
+            let (mut buy, sell) = (side(100.0, -0.1), side(100.1, 0.1));
+            let mut history: Vec<(i64, BookSide, BookSide)> = Vec::new();
+            let mut count = 0;
+            let mut push = || {
+                count += 1;
+                let time = count as i64 * interval;
+                tick(&mut buy, count);
+                history.push((time, buy.clone(), sell.clone()));
+                history.retain(|(ts, _, _)| *ts > time - window);
+                history.len()
+            };
+            for _ in 0..warm {
+                push();
+            }
+            b.iter(|| black_box(push()));
+        });
+
+        group.bench_function(BenchmarkId::new("ring_buffer", label), |b| {
+            let mut state = OrderBookState::with_history(window, BucketStat::default(), DEFAULT_HISTORY_LIMIT_MB << 20);
+            (state.buy, state.sell) = (side(100.0, -0.1), side(100.1, 0.1));
+            let mut count = 0;
+            let mut push = || {
+                count += 1;
+                tick(&mut state.buy, count);
+                state.update_history(count as i64 * interval);
+                state.history().len()
+            };
+            for _ in 0..warm {
+                push();
+            }
+            b.iter(|| black_box(push()));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update_history);
+criterion_main!(benches);
@@ -0,0 +1,233 @@
+// ヒートマップの軸（時刻と価格の目盛り）
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use svg::node::element::{Group, Line, Text};
+use svg::node::Text as TextNode;
+
+// 目盛りの間隔の候補（ミリ秒）。切りのよい時刻に揃える
+const TIME_STEPS: [i64; 16] = [
+    1_000, 2_000, 5_000, 10_000, 15_000, 30_000,
+    60_000, 2 * 60_000, 5 * 60_000, 10 * 60_000, 15 * 60_000, 30 * 60_000,
+    3_600_000, 2 * 3_600_000, 3 * 3_600_000, 6 * 3_600_000,
+];
+const MIN_LABEL_SPACING: f64 = 110.0;  // ラベルどうしの最小間隔（HH:MM:SS が重ならない幅）
+const LABEL_HALF_WIDTH: f64 = 30.0;  // 端で切れるラベルは描かない
+const TICK_LENGTH: i32 = 6;
+// 価格の目盛りの間隔の候補（×10^n）
+const NICE_STEPS: [f64; 4] = [1.0, 2.0, 5.0, 10.0];
+
+/// 時刻ラベルのタイムゾーン
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Zone {
+    #[default]
+    Local,
+    Utc,
+}
+
+impl Zone {
+    /// UTCからのずれ（ミリ秒）
+    fn offset_ms(&self, time: i64) -> i64 {
+        match (self, DateTime::from_timestamp_millis(time)) {
+            (Zone::Local, Some(utc)) => Local.offset_from_utc_datetime(&utc.naive_utc()).local_minus_utc() as i64 * 1000,
+            _ => 0,
+        }
+    }
+
+    /// HH:MM:SS
+    pub fn label(&self, time: i64) -> String {
+        let Some(utc) = DateTime::from_timestamp_millis(time) else {
+            return String::new();
+        };
+        match self {
+            Zone::Local => utc.with_timezone(&Local).format("%H:%M:%S").to_string(),
+            Zone::Utc => utc.format("%H:%M:%S").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeAxis {
+    pub zone: Zone,
+    // 目盛りの位置に縦の補助線を引く
+    pub grid: bool,
+}
+
+/// max_ticks 個以内に収まる、最も細かい目盛りの間隔
+fn time_step(span: i64, max_ticks: i64) -> i64 {
+    let max_ticks = max_ticks.max(1);
+    TIME_STEPS
+        .into_iter()
+        .find(|step| span / step <= max_ticks)
+        .unwrap_or(TIME_STEPS[TIME_STEPS.len() - 1])
+}
+
+impl TimeAxis {
+    /// since〜until の目盛りの時刻（表示するタイムゾーンで切りのよい時刻）
+    pub fn ticks(&self, (since, until): (i64, i64), max_ticks: i64) -> Vec<i64> {
+        if until <= since {
+            return Vec::new();
+        }
+        let step = time_step(until - since, max_ticks);
+        let offset = self.zone.offset_ms(since);
+        let first = (since + offset).div_euclid(step) * step - offset;
+        (0..)
+            .map(|i| first + i * step)
+            .skip_while(|time| *time < since)
+            .take_while(|time| *time <= until)
+            .collect()
+    }
+
+    /// since から window の時間を x〜x + width に対応させて、axis_y の下に目盛りとラベルを描く
+    /// 補助線は top から axis_y まで引く
+    pub fn render(&self, (since, window): (i64, i64), (x, width): (i32, i32), (top, axis_y): (i32, i32)) -> Group {
+        let mut group = Group::new()
+            .set("font-family", "Arial")
+            .set("font-size", "12")
+            .set("fill", "white")
+            .add(Line::new()
+                .set("x1", x)
+                .set("x2", x + width)
+                .set("y1", axis_y)
+                .set("y2", axis_y)
+                .set("stroke", "rgba(255, 255, 255, 0.5)")
+                .set("stroke-width", 1));
+
+        let max_ticks = (width as f64 / MIN_LABEL_SPACING) as i64;
+        for time in self.ticks((since, since + window), max_ticks) {
+            let tick_x = x as f64 + (time - since) as f64 / window as f64 * width as f64;
+            if self.grid {
+                group = group.add(Line::new()
+                    .set("x1", tick_x)
+                    .set("x2", tick_x)
+                    .set("y1", top)
+                    .set("y2", axis_y)
+                    .set("stroke", "rgba(255, 255, 255, 0.15)")
+                    .set("stroke-width", 1));
+            }
+            group = group.add(Line::new()
+                .set("x1", tick_x)
+                .set("x2", tick_x)
+                .set("y1", axis_y)
+                .set("y2", axis_y + TICK_LENGTH)
+                .set("stroke", "white")
+                .set("stroke-width", 1));
+            if tick_x - LABEL_HALF_WIDTH < x as f64 || tick_x + LABEL_HALF_WIDTH > (x + width) as f64 {
+                continue;
+            }
+            group = group.add(Text::new()
+                .set("x", tick_x)
+                .set("y", axis_y + TICK_LENGTH + 12)
+                .set("text-anchor", "middle")
+                .add(TextNode::new(self.zone.label(time))));
+        }
+        group
+    }
+}
+
+/// 10^n 単位で見たときの小数桁数（浮動小数点の誤差で1桁増えないよう少し丸める）
+fn decimals_of(step: f64) -> usize {
+    (-(step.log10() + 1e-9).floor()).max(0.0) as usize
+}
+
+/// 価格軸の目盛り
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTicks {
+    pub prices: Vec<f64>,
+    // ラベルの小数桁数
+    pub decimals: usize,
+}
+
+impl PriceTicks {
+    /// min〜max に max_ticks 個程度、1・2・5×10^n の間隔で目盛りを置く
+    /// 価格の刻みが分かれば間隔をその倍数にし、ラベルの桁数も実際の価格に合わせる
+    pub fn new((min, max): (f64, f64), max_ticks: usize, tick: Option<f64>) -> Self {
+        if !(min.is_finite() && max.is_finite() && max > min) {
+            return Self { prices: Vec::new(), decimals: 0 };
+        }
+        let raw = (max - min) / max_ticks.max(1) as f64;
+        let magnitude = 10f64.powf(raw.log10().floor());
+        let mut step = NICE_STEPS
+            .into_iter()
+            .map(|m| m * magnitude)
+            .find(|step| *step >= raw * (1.0 - 1e-9))
+            .unwrap_or(10.0 * magnitude);
+        if let Some(tick) = tick.filter(|tick| *tick > 0.0) {
+            step = (step / tick - 1e-9).ceil().max(1.0) * tick;
+        }
+        let (first, last) = ((min / step - 1e-9).ceil() as i64, (max / step + 1e-9).floor() as i64);
+        Self {
+            prices: (first..=last).map(|k| k as f64 * step).collect(),
+            decimals: decimals_of(step),
+        }
+    }
+
+    pub fn label(&self, price: f64) -> String {
+        format!("{:.*}", self.decimals, price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UTC: TimeAxis = TimeAxis { zone: Zone::Utc, grid: false };
+
+    #[test]
+    fn step_is_finest_that_fits() {
+        assert_eq!(time_step(30_000, 10), 5_000);
+        assert_eq!(time_step(300_000, 10), 30_000);
+        assert_eq!(time_step(3_600_000, 10), 10 * 60_000);
+        assert_eq!(time_step(6 * 3_600_000, 12), 30 * 60_000);
+        // 収まらなければ最も粗い間隔
+        assert_eq!(time_step(1_000 * 3_600_000, 10), 6 * 3_600_000);
+    }
+
+    #[test]
+    fn ticks_are_rounded_times() {
+        // 2024-01-01 12:34:56.789 UTC から5分
+        let since = 1_704_112_496_789;
+        let ticks = UTC.ticks((since, since + 300_000), 10);
+        assert_eq!(ticks.len(), 10);
+        assert!(ticks.iter().all(|t| t % 30_000 == 0));
+        assert_eq!(UTC.zone.label(ticks[0]), "12:35:00");
+        assert_eq!(UTC.zone.label(ticks[9]), "12:39:30");
+        assert!(UTC.ticks((since, since), 10).is_empty());
+    }
+
+    fn labels(ticks: &PriceTicks) -> Vec<String> {
+        ticks.prices.iter().map(|p| ticks.label(*p)).collect()
+    }
+
+    #[test]
+    fn price_ticks_are_nice_numbers() {
+        let ticks = PriceTicks::new((24.0137, 24.9862), 10, None);
+        assert_eq!(labels(&ticks), ["24.1", "24.2", "24.3", "24.4", "24.5", "24.6", "24.7", "24.8", "24.9"]);
+        assert!(PriceTicks::new((1.0, 1.0), 10, None).prices.is_empty());
+    }
+
+    #[test]
+    fn large_prices_snap_to_integers() {
+        // BTCは有効数字5桁なので刻みは1
+        let ticks = PriceTicks::new((99_000.0, 101_000.0), 10, Some(1.0));
+        assert_eq!(ticks.decimals, 0);
+        assert_eq!(labels(&ticks).first().map(String::as_str), Some("99000"));
+        assert_eq!(labels(&ticks).last().map(String::as_str), Some("101000"));
+        assert_eq!(ticks.prices.len(), 11);
+        // 狭い範囲でも刻みより細かくはしない
+        let ticks = PriceTicks::new((100_000.0, 100_004.0), 10, Some(1.0));
+        assert_eq!(labels(&ticks), ["100000", "100001", "100002", "100003", "100004"]);
+    }
+
+    #[test]
+    fn small_prices_keep_significant_digits() {
+        let ticks = PriceTicks::new((0.0000123, 0.0000131), 10, Some(0.00000001));
+        assert_eq!(ticks.decimals, 7);
+        assert_eq!(labels(&ticks).first().map(String::as_str), Some("0.0000123"));
+        assert_eq!(labels(&ticks).last().map(String::as_str), Some("0.0000131"));
+        // 刻みが粗い銘柄では、その刻みの価格だけに目盛りを置く
+        let ticks = PriceTicks::new((0.0000123, 0.0000131), 10, Some(0.000001));
+        assert_eq!(labels(&ticks), ["0.000013"]);
+    }
+}
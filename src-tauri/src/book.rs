@@ -0,0 +1,751 @@
+// 銘柄ごとの板の状態と履歴
+use crate::axis::TimeAxis;
+use crate::colormap::ColorMap;
+use crate::feed::{Aggregation, Side, Subscription, WsTrade};
+use crate::history::{BucketStat, History, Snapshot};
+use crate::intensity::IntensityScale;
+use crate::market::Market;
+use crate::viewport::{PriceViewport, Recenter, TimeViewport, ViewAxes, ViewRange};
+use log::error;
+use ordered_float::OrderedFloat;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+
+// 表示する時間幅（履歴・約定の保持期間）の既定値と範囲
+pub const DEFAULT_WINDOW_MS: i64 = 300_000;
+const MIN_WINDOW_MS: i64 = 30_000;
+pub const MAX_WINDOW_MS: i64 = 6 * 60 * 60 * 1000;
+
+// 1銘柄の履歴に使うメモリの上限（MB）の既定値と範囲。購読している集約レベルで等分する
+pub const DEFAULT_HISTORY_LIMIT_MB: usize = 64;
+const MIN_HISTORY_LIMIT_MB: usize = 4;
+const MAX_HISTORY_LIMIT_MB: usize = 1024;
+
+// 自動選択で並行して購読する集約レベル（細かい順）
+const AUTO_AGGREGATIONS: [Aggregation; 4] = [
+    Aggregation::sig_figs(5),
+    Aggregation::sig_figs(4),
+    Aggregation::sig_figs(3),
+    Aggregation::sig_figs(2),
+];
+// 表示範囲のうち、この割合以上を板がカバーしていれば十分とみなす
+const MIN_COVERAGE: f64 = 0.9;
+
+/// 購読する集約の選び方
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum AggregationMode {
+    // 1つの集約だけを購読する
+    Fixed(Aggregation),
+    // 複数の集約を並行して購読し、表示範囲に合わせて描画する集約を選ぶ
+    Auto,
+}
+
+impl Default for AggregationMode {
+    fn default() -> Self {
+        Self::Fixed(Aggregation::default())
+    }
+}
+
+impl AggregationMode {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Fixed(aggregation) => aggregation.validate(),
+            Self::Auto => Ok(()),
+        }
+    }
+
+    fn aggregations(&self) -> Vec<Aggregation> {
+        match self {
+            Self::Fixed(aggregation) => vec![*aggregation],
+            Self::Auto => AUTO_AGGREGATIONS.to_vec(),
+        }
+    }
+}
+
+/// 1つの価格レベルの数量と注文数
+#[derive(Debug, Clone, Copy)]
+pub struct Level {
+    pub size: f64,
+    pub orders: i32,
+}
+
+/// ヒートマップの濃淡に使う量
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum HeatmapMetric {
+    // 価格レベルの数量
+    #[default]
+    Size,
+    // 1注文あたりの平均数量（sz / n）。大口1件の壁と小口の集まりを見分ける
+    AverageOrderSize,
+}
+
+impl HeatmapMetric {
+    pub fn value(&self, level: &Level) -> f64 {
+        match self {
+            Self::Size => level.size,
+            Self::AverageOrderSize => level.size / level.orders.max(1) as f64,
+        }
+    }
+}
+
+/// 銘柄ごとにフロントエンドへ送るデータの種類
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum HeatmapOutput {
+    // Rustで描画した画像（PNG + 補助表示のSVG）
+    #[default]
+    Image,
+    // 時間×価格の数値グリッド（描画はフロントエンドで行う）
+    Grid,
+}
+
+// 片側の板（価格 → 数量と注文数）
+pub type BookSide = BTreeMap<OrderedFloat<f64>, Level>;
+
+pub fn validate_window(window_ms: i64) -> Result<(), String> {
+    if !(MIN_WINDOW_MS..=MAX_WINDOW_MS).contains(&window_ms) {
+        return Err(format!("time window must be between {} and {} ms: {}", MIN_WINDOW_MS, MAX_WINDOW_MS, window_ms));
+    }
+    Ok(())
+}
+
+pub fn validate_history_limit(limit_mb: usize) -> Result<(), String> {
+    if !(MIN_HISTORY_LIMIT_MB..=MAX_HISTORY_LIMIT_MB).contains(&limit_mb) {
+        return Err(format!("history limit must be between {} and {} MB: {}", MIN_HISTORY_LIMIT_MB, MAX_HISTORY_LIMIT_MB, limit_mb));
+    }
+    Ok(())
+}
+
+pub struct OrderBookState {
+    pub buy: BookSide,
+    pub sell: BookSide,
+    history: History,
+}
+
+impl Default for OrderBookState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderBookState {
+    pub fn new() -> Self {
+        Self::with_history(DEFAULT_WINDOW_MS, BucketStat::default(), DEFAULT_HISTORY_LIMIT_MB << 20)
+    }
+
+    pub fn with_history(window: i64, stat: BucketStat, max_bytes: usize) -> Self {
+        Self {
+            buy: BTreeMap::new(),
+            sell: BTreeMap::new(),
+            history: History::new(window, stat, max_bytes),
+        }
+    }
+
+    /// 時間幅内のスナップショット（古い順）
+    pub fn history(&self) -> &[Snapshot] {
+        self.history.snapshots()
+    }
+
+    /// since〜until にかかるスナップショット（since の時点の板を表す1つ前のものを含む）
+    pub fn history_between(&self, (since, until): (i64, i64)) -> &[Snapshot] {
+        let history = self.history();
+        let start = history.partition_point(|(ts, _, _)| *ts <= since).saturating_sub(1);
+        let end = history.partition_point(|(ts, _, _)| *ts <= until);
+        &history[start..end.max(start)]
+    }
+
+    pub fn window(&self) -> i64 {
+        self.history.window()
+    }
+
+    /// 履歴が組み直されて過去のスナップショットが変わるたびに増える
+    pub fn history_revision(&self) -> u64 {
+        self.history.revision()
+    }
+
+    pub fn update_history(&mut self, timestamp: i64) {
+        self.history.push(timestamp, &self.buy, &self.sell);
+    }
+
+    /// 現在の最良気配の中間（片側しかなければその最良気配）
+    pub fn mid(&self) -> Option<f64> {
+        let bid = self.buy.keys().next_back().map(|p| p.into_inner());
+        let ask = self.sell.keys().next().map(|p| p.into_inner());
+        match (bid, ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            (bid, ask) => bid.or(ask),
+        }
+    }
+
+    /// 現在の板で隣り合う価格の最小間隔
+    pub fn price_gap(&self) -> Option<f64> {
+        let mut prices: Vec<f64> = self.buy.keys().chain(self.sell.keys()).map(|p| p.into_inner()).collect();
+        prices.sort_by(f64::total_cmp);
+        prices
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|gap| *gap > 0.0)
+            .min_by(f64::total_cmp)
+    }
+
+    /// since〜until のスナップショットに現れた価格の範囲
+    pub fn price_range_between(&self, range: (i64, i64)) -> Option<(f64, f64)> {
+        let (min, max) = self.history_between(range).iter()
+            .flat_map(|(_, buy, sell)| buy.keys().chain(sell.keys()))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| (min.min(p.0), max.max(p.0)));
+        (min <= max).then_some((min, max))
+    }
+
+    /// 現在の板が表示範囲のどれだけをカバーしているか（0.0〜1.0）
+    fn coverage(&self, (low, high): (f64, f64)) -> f64 {
+        let (Some(min), Some(max)) = (
+            self.buy.keys().next().or(self.sell.keys().next()),
+            self.sell.keys().next_back().or(self.buy.keys().next_back()),
+        ) else {
+            return 0.0;
+        };
+        let overlap = high.min(max.0) - low.max(min.0);
+        (overlap / (high - low)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub time: i64,
+    pub price: f64,
+    pub size: f64,
+    pub side: Side,
+    pub tid: u64,
+    // この約定までの累積出来高デルタ（買い - 売り）
+    pub cvd: f64,
+}
+
+impl Trade {
+    fn delta(&self) -> f64 {
+        match self.side {
+            Side::Buy => self.size,
+            Side::Sell => -self.size,
+        }
+    }
+}
+
+/// 直近の約定（時刻順、保持期間は履歴と同じ）
+pub struct TradeWindow {
+    trades: VecDeque<Trade>,
+    // 再購読時のスナップショットで同じ約定が重複しないようにする
+    tids: HashSet<u64>,
+    // 保持期間外に捨てた約定までの累積デルタ（CVDが古い約定の削除でずれないようにする）
+    base_cvd: f64,
+    // この時刻以前の約定は捨てて base_cvd に数えたので、再購読で届き直しても入れない
+    pruned_until: i64,
+    window: i64,
+}
+
+impl TradeWindow {
+    pub fn new(window: i64) -> Self {
+        Self {
+            trades: VecDeque::new(),
+            tids: HashSet::new(),
+            base_cvd: 0.0,
+            pruned_until: i64::MIN,
+            window,
+        }
+    }
+
+    fn set_window(&mut self, window: i64) {
+        self.window = window;
+        self.prune();
+    }
+
+    /// 指定時刻までの累積出来高デルタ
+    pub fn cvd_at(&self, time: i64) -> f64 {
+        match self.trades.partition_point(|t| t.time <= time) {
+            0 => self.base_cvd,
+            i => self.trades[i - 1].cvd,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trade> {
+        self.trades.iter()
+    }
+
+    pub fn push(&mut self, trades: &[WsTrade]) {
+        // CVDを再計算する必要がある最初の位置
+        let mut dirty = self.trades.len();
+        for trade in trades {
+            if self.tids.contains(&trade.tid) || trade.time <= self.pruned_until {
+                continue;
+            }
+            match (trade.px.parse::<f64>(), trade.sz.parse::<f64>()) {
+                (Ok(price), Ok(size)) => {
+                    // 時刻順を保って挿入（ほとんどの場合は末尾）
+                    let index = self.trades.partition_point(|t| t.time <= trade.time);
+                    self.trades.insert(index, Trade {
+                        time: trade.time,
+                        price,
+                        size,
+                        side: trade.side,
+                        tid: trade.tid,
+                        cvd: 0.0,
+                    });
+                    self.tids.insert(trade.tid);
+                    dirty = dirty.min(index);
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("Failed to parse trade price or size: {:?}", e);
+                }
+            }
+        }
+
+        // 挿入位置以降の累積デルタを更新
+        let mut cvd = match dirty {
+            0 => self.base_cvd,
+            i => self.trades[i - 1].cvd,
+        };
+        for trade in self.trades.range_mut(dirty..) {
+            cvd += trade.delta();
+            trade.cvd = cvd;
+        }
+
+        self.prune();
+    }
+
+    /// 保持期間より古い約定を削除する
+    fn prune(&mut self) {
+        let Some(latest) = self.trades.back().map(|t| t.time) else {
+            return;
+        };
+        self.pruned_until = self.pruned_until.max(latest - self.window);
+        while let Some(trade) = self.trades.front() {
+            if trade.time > latest - self.window {
+                break;
+            }
+            self.base_cvd = trade.cvd;
+            self.tids.remove(&trade.tid);
+            self.trades.pop_front();
+        }
+    }
+}
+
+/// 1銘柄分の状態（購読している集約レベルごとに板と履歴を持つ）
+pub struct CoinBook {
+    pub mode: AggregationMode,
+    pub metric: HeatmapMetric,
+    pub scale: IntensityScale,
+    pub colors: ColorMap,
+    pub time_axis: TimeAxis,
+    pub output: HeatmapOutput,
+    pub trades: TradeWindow,
+    viewport: PriceViewport,
+    // 最後に決めた表示価格範囲（None は履歴から自動）と、Midに追従するときの中心
+    price_range: Option<(f64, f64)>,
+    recenter: Recenter,
+    // 保持期間のうち表示する時間範囲
+    time_viewport: TimeViewport,
+    // 表示する時間幅（ミリ秒）
+    window: i64,
+    // 集計した区間を描画するときに使う値
+    stat: BucketStat,
+    // 履歴に使うメモリの上限（MB）
+    history_limit_mb: usize,
+    levels: Vec<(Aggregation, OrderBookState)>,
+    // 描画のときに選んだ集約レベル（選ぶには履歴を調べるので、受信のたびには選び直さない）
+    visible: Option<Aggregation>,
+    // 板と履歴を作り直した時刻（これより前に受信した板の更新は捨てる）
+    reset_at: Instant,
+}
+
+impl CoinBook {
+    pub fn new(mode: AggregationMode) -> Self {
+        let mut book = Self {
+            mode,
+            metric: HeatmapMetric::default(),
+            scale: IntensityScale::default(),
+            colors: ColorMap::default(),
+            time_axis: TimeAxis::default(),
+            output: HeatmapOutput::default(),
+            trades: TradeWindow::new(DEFAULT_WINDOW_MS),
+            viewport: PriceViewport::default(),
+            price_range: None,
+            recenter: Recenter::default(),
+            time_viewport: TimeViewport::default(),
+            window: DEFAULT_WINDOW_MS,
+            stat: BucketStat::default(),
+            history_limit_mb: DEFAULT_HISTORY_LIMIT_MB,
+            levels: Vec::new(),
+            visible: None,
+            reset_at: Instant::now(),
+        };
+        book.reset_levels();
+        book
+    }
+
+    /// 集約の選び方に従って、空の板と履歴を作り直す
+    fn reset_levels(&mut self) {
+        let aggregations = self.mode.aggregations();
+        let max_bytes = self.level_limit(aggregations.len());
+        self.levels = aggregations
+            .into_iter()
+            .map(|a| (a, OrderBookState::with_history(self.window, self.stat, max_bytes)))
+            .collect();
+        self.visible = None;
+        self.reset_at = Instant::now();
+    }
+
+    /// 板と履歴を作り直す前に受信した更新か
+    /// 作り直す前にチャンネルに溜まっていた更新を、新しい履歴に混ぜないようにする
+    pub fn is_stale(&self, received: Instant) -> bool {
+        received < self.reset_at
+    }
+
+    /// 集約レベル1つあたりの履歴のメモリ上限（バイト）
+    fn level_limit(&self, levels: usize) -> usize {
+        (self.history_limit_mb << 20) / levels.max(1)
+    }
+
+    pub fn window(&self) -> i64 {
+        self.window
+    }
+
+    /// 表示する時間幅を変更する（履歴・約定の保持期間も合わせて変わる）
+    pub fn set_window(&mut self, window: i64) {
+        self.window = window;
+        self.trades.set_window(window);
+        for (_, state) in self.levels.iter_mut() {
+            state.history.set_window(window);
+        }
+    }
+
+    pub fn stat(&self) -> BucketStat {
+        self.stat
+    }
+
+    pub fn set_stat(&mut self, stat: BucketStat) {
+        self.stat = stat;
+        for (_, state) in self.levels.iter_mut() {
+            state.history.set_stat(stat);
+        }
+    }
+
+    pub fn history_limit_mb(&self) -> usize {
+        self.history_limit_mb
+    }
+
+    /// 履歴に使うメモリの上限を変更する（超えている分は古い履歴から捨てる）
+    pub fn set_history_limit(&mut self, limit_mb: usize) {
+        self.history_limit_mb = limit_mb;
+        let max_bytes = self.level_limit(self.levels.len());
+        for (_, state) in self.levels.iter_mut() {
+            state.history.set_max_bytes(max_bytes);
+        }
+    }
+
+    /// 履歴が使っているメモリの概算（バイト）
+    pub fn history_bytes(&self) -> usize {
+        self.levels.iter().map(|(_, state)| state.history.bytes()).sum()
+    }
+
+    /// 集約の選び方を変更し、板と履歴を破棄する（約定は集約に依存しないので残す）
+    pub fn set_mode(&mut self, mode: AggregationMode) {
+        self.mode = mode;
+        self.reset_levels();
+    }
+
+    pub fn viewport(&self) -> PriceViewport {
+        self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: PriceViewport) {
+        self.viewport = viewport;
+        self.recenter.reset();
+        self.price_range = match viewport {
+            PriceViewport::Locked { min, max } => Some((min, max)),
+            _ => None,
+        };
+    }
+
+    /// 表示する価格範囲（None は描画する集約レベルの履歴から自動）
+    pub fn price_range(&self) -> Option<(f64, f64)> {
+        self.price_range
+    }
+
+    /// 表示方法と現在の板から表示価格範囲と描画する集約レベルを決め直す（描画のたびに呼ぶ）
+    /// Midを中心にする場合、刻みは銘柄の情報から、分からなければ板の価格間隔から求める
+    pub fn update_view(&mut self, market: Option<&Market>) -> Option<(f64, f64)> {
+        self.price_range = match self.viewport {
+            PriceViewport::Auto => None,
+            PriceViewport::Locked { min, max } => Some((min, max)),
+            viewport => self.levels.iter()
+                .map(|(_, state)| state)
+                .find_map(|state| state.mid().map(|mid| (state, mid)))
+                .and_then(|(state, mid)| {
+                    let tick = market.map(|market| market.tick_at(mid)).or_else(|| state.price_gap());
+                    let half = viewport.half_width(mid, tick).filter(|half| *half > 0.0)?;
+                    Some(self.recenter.range(mid, half))
+                }),
+        };
+        self.visible = self.choose_level();
+        self.price_range
+    }
+
+    pub fn time_viewport(&self) -> TimeViewport {
+        self.time_viewport
+    }
+
+    /// 描画する集約レベルの最新のスナップショットの時刻
+    fn latest(&self) -> Option<i64> {
+        self.visible_level()?.1.history().last().map(|(ts, _, _)| *ts)
+    }
+
+    /// 集約レベルの最新のスナップショットから求めた、表示する時間範囲
+    fn times_of(&self, state: &OrderBookState) -> Option<(i64, i64)> {
+        let latest = state.history().last()?.0;
+        Some(self.time_viewport.range(latest, self.window))
+    }
+
+    /// 表示する時間範囲と価格範囲（履歴がなければ None）
+    /// 価格範囲が自動のときは、表示する時間範囲の履歴に現れた価格の範囲にする
+    pub fn view_range(&self) -> Option<ViewRange> {
+        let (_, state) = self.visible_level()?;
+        let (since, until) = self.times_of(state)?;
+        let (min_price, max_price) = self.price_range.or_else(|| state.price_range_between((since, until)))?;
+        Some(ViewRange { since, until, min_price, max_price })
+    }
+
+    /// (time, price) の位置を保ったまま、表示範囲を factor 倍にする（1.0より大きければ広く表示）
+    /// 価格方向を変えると表示価格範囲は固定になる
+    pub fn zoom(&mut self, (time, price): (i64, f64), factor: f64, axes: ViewAxes) {
+        let (Some(view), Some(latest)) = (self.view_range(), self.latest()) else {
+            return;
+        };
+        let (min, max) = view.prices();
+        if axes.time() {
+            self.time_viewport.zoom(view.times(), time, factor, (latest, self.window));
+        }
+        if axes.price() {
+            let price = price.clamp(min, max);
+            let viewport = PriceViewport::Locked {
+                min: price - (price - min) * factor,
+                max: price + (max - price) * factor,
+            };
+            // 拡大しすぎて幅がなくなる場合はそのままにする
+            if viewport.validate().is_ok() {
+                self.set_viewport(viewport);
+            }
+        }
+    }
+
+    /// 表示範囲を時間 delta_time ミリ秒、価格 delta_price だけずらす
+    pub fn pan(&mut self, (delta_time, delta_price): (i64, f64), axes: ViewAxes) {
+        let (Some(view), Some(latest)) = (self.view_range(), self.latest()) else {
+            return;
+        };
+        if axes.time() {
+            self.time_viewport.pan(view.times(), delta_time, (latest, self.window));
+        }
+        if axes.price() && delta_price != 0.0 {
+            self.set_viewport(PriceViewport::Locked { min: view.min_price + delta_price, max: view.max_price + delta_price });
+        }
+    }
+
+    /// 保持期間全体・自動の価格範囲に戻す
+    pub fn reset_view(&mut self) {
+        self.time_viewport = TimeViewport::default();
+        self.set_viewport(PriceViewport::Auto);
+    }
+
+    pub fn level_mut(&mut self, aggregation: Aggregation) -> Option<&mut OrderBookState> {
+        self.levels.iter_mut().find(|(a, _)| *a == aggregation).map(|(_, state)| state)
+    }
+
+    /// 描画する集約レベル（最後に描画したときに選んだもの。まだ描画していなければその場で選ぶ）
+    pub fn visible_level(&self) -> Option<(Aggregation, &OrderBookState)> {
+        let aggregation = self.visible.or_else(|| self.choose_level())?;
+        self.levels.iter().find(|(a, _)| *a == aggregation).map(|(a, state)| (*a, state))
+    }
+
+    /// 描画する集約レベルを選ぶ
+    /// 表示範囲を十分にカバーしている中で最も細かいものを使い、
+    /// どれもカバーしていなければ最もカバー率の高いものを使う
+    /// 価格範囲が自動のときは、最も粗い集約レベルの表示する時間範囲の履歴に現れた価格範囲を表示範囲とみなす
+    fn choose_level(&self) -> Option<Aggregation> {
+        let levels = self.levels.iter().filter(|(_, state)| !state.history().is_empty());
+        let range = self.price_range.or_else(|| {
+            let (_, state) = levels.clone().next_back()?;
+            state.price_range_between(self.times_of(state)?)
+        });
+        let Some(range) = range else {
+            return levels.clone().next().map(|(a, _)| *a);
+        };
+
+        let mut best: Option<(f64, Aggregation)> = None;
+        for (aggregation, state) in levels {
+            let coverage = state.coverage(range);
+            if coverage >= MIN_COVERAGE {
+                return Some(*aggregation);
+            }
+            if best.is_none_or(|(c, _)| coverage > c) {
+                best = Some((coverage, *aggregation));
+            }
+        }
+        best.map(|(_, aggregation)| aggregation)
+    }
+}
+
+// 銘柄ごとの板の状態（キーは購読に使うcoin）
+pub type BookMap = BTreeMap<String, Arc<RwLock<CoinBook>>>;
+
+/// 集約レベル（接続）ごとに購読すべきチャンネルの一覧
+/// 約定は集約に依存しないため、銘柄の最初の集約レベルの接続でだけ購読する
+pub fn subscriptions(books: &RwLock<BookMap>) -> BTreeMap<Aggregation, BTreeSet<Subscription>> {
+    let mut subscriptions: BTreeMap<Aggregation, BTreeSet<Subscription>> = BTreeMap::new();
+    for (coin, book) in books.read().iter() {
+        let book = book.read();
+        for (i, (aggregation, _)) in book.levels.iter().enumerate() {
+            let channels = subscriptions.entry(*aggregation).or_default();
+            channels.insert(Subscription::L2Book(coin.clone()));
+            if i == 0 {
+                channels.insert(Subscription::Trades(coin.clone()));
+            }
+        }
+    }
+    subscriptions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{side, trade};
+
+    #[test]
+    fn drops_updates_received_before_reset() {
+        let mut book = CoinBook::new(AggregationMode::default());
+        let queued = Instant::now();
+        assert!(!book.is_stale(queued));
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        // 集約を変えた後は、それより前に受信してチャンネルに残っていた更新を捨てる
+        book.set_mode(AggregationMode::Auto);
+        assert!(book.is_stale(queued));
+        assert!(!book.is_stale(Instant::now()));
+    }
+
+    fn tids(trades: &TradeWindow) -> Vec<u64> {
+        trades.iter().map(|t| t.tid).collect()
+    }
+
+    #[test]
+    fn trades_ignore_resent_tids() {
+        let mut trades = TradeWindow::new(60_000);
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 2_000, Side::Sell, 100.0, 2.0)]);
+        // 再接続時のスナップショットで同じ約定がもう一度届く
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 2_000, Side::Sell, 100.0, 2.0), trade(3, 3_000, Side::Buy, 100.0, 4.0)]);
+        assert_eq!(tids(&trades), [1, 2, 3]);
+        assert_eq!(trades.cvd_at(3_000), 3.0);
+    }
+
+    #[test]
+    fn trades_stay_in_time_order() {
+        let mut trades = TradeWindow::new(60_000);
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(3, 3_000, Side::Buy, 100.0, 1.0)]);
+        // 遅れて届いた約定は時刻の位置に入る
+        trades.push(&[trade(2, 2_000, Side::Sell, 100.0, 1.0)]);
+        assert_eq!(tids(&trades), [1, 2, 3]);
+        // 同じ時刻の約定は届いた順
+        trades.push(&[trade(4, 2_000, Side::Buy, 100.0, 1.0)]);
+        assert_eq!(tids(&trades), [1, 2, 4, 3]);
+        // 数値にならない約定は捨てる
+        trades.push(&[WsTrade { px: "x".to_string(), ..trade(5, 4_000, Side::Buy, 100.0, 1.0) }]);
+        assert_eq!(tids(&trades), [1, 2, 4, 3]);
+    }
+
+    #[test]
+    fn trades_prune_outside_window() {
+        let mut trades = TradeWindow::new(10_000);
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 5_000, Side::Buy, 100.0, 1.0)]);
+        trades.push(&[trade(3, 12_000, Side::Buy, 100.0, 1.0)]);
+        assert_eq!(tids(&trades), [2, 3]);
+        // 保持期間を縮めるとその場で捨てる
+        trades.set_window(5_000);
+        assert_eq!(tids(&trades), [3]);
+        // 再購読で捨てた約定がもう一度届いても、保持期間より古いので入れない
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 5_000, Side::Buy, 100.0, 1.0)]);
+        assert_eq!(tids(&trades), [3]);
+        // 捨てた約定の tid は覚えておかない
+        trades.push(&[trade(1, 12_500, Side::Buy, 100.0, 1.0)]);
+        assert_eq!(tids(&trades), [3, 1]);
+    }
+
+    #[test]
+    fn cvd_survives_pruning_and_late_trades() {
+        let mut trades = TradeWindow::new(10_000);
+        trades.push(&[trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 5_000, Side::Sell, 100.0, 2.0)]);
+        trades.push(&[trade(3, 12_000, Side::Buy, 100.0, 4.0)]);
+        // 保持期間外に捨てた約定の分も累積に残る
+        assert_eq!(tids(&trades), [2, 3]);
+        assert_eq!(trades.cvd_at(0), 1.0);
+        assert_eq!(trades.cvd_at(5_000), -1.0);
+        assert_eq!(trades.cvd_at(12_000), 3.0);
+
+        // 遅れて届いた約定以降の累積だけが変わる
+        trades.push(&[trade(4, 8_000, Side::Sell, 100.0, 1.0)]);
+        assert_eq!(trades.cvd_at(5_000), -1.0);
+        assert_eq!(trades.cvd_at(8_000), -2.0);
+        assert_eq!(trades.cvd_at(12_000), 2.0);
+
+        // 再購読のスナップショットで、捨てた約定を含めて同じ約定がもう一度届いても二重に数えない
+        let mut trades = TradeWindow::new(10_000);
+        let snapshot = [trade(1, 1_000, Side::Buy, 100.0, 1.0), trade(2, 20_000, Side::Buy, 100.0, 1.0)];
+        trades.push(&snapshot);
+        assert_eq!(trades.cvd_at(20_000), 2.0);
+        trades.push(&snapshot);
+        assert_eq!(tids(&trades), [2]);
+        assert_eq!(trades.cvd_at(20_000), 2.0);
+    }
+
+    fn set_book(book: &mut CoinBook, aggregation: Aggregation, (low, high): (f64, f64), time: i64) {
+        let state = book.level_mut(aggregation).unwrap();
+        state.buy = side(&[(low, 1.0, 1)]);
+        state.sell = side(&[(high, 1.0, 1)]);
+        state.update_history(time);
+    }
+
+    #[test]
+    fn auto_price_range_follows_time_zoom() {
+        let aggregation = Aggregation::default();
+        let mut book = CoinBook::new(AggregationMode::Fixed(aggregation));
+        for (ts, range) in [(1_000, (50.0, 150.0)), (40_000, (99.0, 101.0)), (50_000, (99.5, 100.5)), (60_000, (98.0, 102.0))] {
+            set_book(&mut book, aggregation, range, ts);
+        }
+        let view = |since, until, min_price, max_price| Some(ViewRange { since, until, min_price, max_price });
+        assert_eq!(book.view_range(), view(60_000 - DEFAULT_WINDOW_MS, 60_000, 50.0, 150.0));
+
+        // 時間だけを拡大すると、その時間範囲（左端の時点の板を含む）の価格の幅に合わせる
+        book.zoom((60_000, 100.0), 0.04, ViewAxes::Time);
+        assert_eq!(book.view_range(), view(48_000, 60_000, 98.0, 102.0));
+    }
+
+    #[test]
+    fn auto_mode_picks_level_covering_visible_range() {
+        let mut book = CoinBook::new(AggregationMode::Auto);
+        // 細かい集約ほど板の価格の幅が狭い
+        for (aggregation, range) in AUTO_AGGREGATIONS.into_iter().zip([(99.0, 101.0), (95.0, 105.0), (80.0, 120.0), (80.0, 120.0)]) {
+            set_book(&mut book, aggregation, range, 1_000);
+        }
+        // 自動の価格範囲（最も粗い集約の履歴 80〜120）をカバーする中で最も細かいもの
+        assert_eq!(book.visible_level().map(|(a, _)| a), Some(AUTO_AGGREGATIONS[2]));
+
+        book.update_view(None);
+        assert_eq!(book.visible_level().map(|(a, _)| a), Some(AUTO_AGGREGATIONS[2]));
+
+        // 狭い範囲に固定すれば最も細かいもの（選び直すのは次の描画のとき）
+        book.set_viewport(PriceViewport::Locked { min: 99.5, max: 100.5 });
+        assert_eq!(book.visible_level().map(|(a, _)| a), Some(AUTO_AGGREGATIONS[2]));
+        book.update_view(None);
+        assert_eq!(book.visible_level().map(|(a, _)| a), Some(AUTO_AGGREGATIONS[0]));
+    }
+}
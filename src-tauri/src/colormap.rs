@@ -0,0 +1,199 @@
+// ヒートマップのセルの配色
+// 強度（0.0〜1.0）をグラデーションで色に変換する。描画のたびに補間しないよう、256段階の表にしてから使う
+use crate::raster::Color;
+use serde::{Deserialize, Serialize};
+
+const RAMP_STEPS: usize = 256;
+const MAX_STOPS: usize = 32;
+
+// 知覚的に均等なグラデーション（matplotlib の viridis / inferno を5点で近似）
+const VIRIDIS: [(f64, Color); 5] = [
+    (0.0, Color::rgba(68, 1, 84, 1.0)),
+    (0.25, Color::rgba(59, 82, 139, 1.0)),
+    (0.5, Color::rgba(33, 145, 140, 1.0)),
+    (0.75, Color::rgba(94, 201, 98, 1.0)),
+    (1.0, Color::rgba(253, 231, 37, 1.0)),
+];
+const INFERNO: [(f64, Color); 5] = [
+    (0.0, Color::rgba(0, 0, 4, 1.0)),
+    (0.25, Color::rgba(87, 16, 110, 1.0)),
+    (0.5, Color::rgba(188, 55, 84, 1.0)),
+    (0.75, Color::rgba(249, 142, 9, 1.0)),
+    (1.0, Color::rgba(252, 255, 164, 1.0)),
+];
+// 薄い板は暗い青、厚くなるほど黄色から赤へ
+const BOOKMAP: [(f64, Color); 5] = [
+    (0.0, Color::rgba(0, 0, 80, 1.0)),
+    (0.35, Color::rgba(0, 120, 255, 1.0)),
+    (0.6, Color::rgba(255, 255, 0, 1.0)),
+    (0.8, Color::rgba(255, 128, 0, 1.0)),
+    (1.0, Color::rgba(255, 0, 0, 1.0)),
+];
+// 従来の配色（黒背景に不透明度だけで濃淡を付ける）
+const CLASSIC_BID: [(f64, Color); 2] = [(0.0, Color::rgba(0, 255, 0, 0.0)), (1.0, Color::rgba(0, 255, 0, 1.0))];
+const CLASSIC_ASK: [(f64, Color); 2] = [(0.0, Color::rgba(255, 0, 0, 0.0)), (1.0, Color::rgba(255, 0, 0, 1.0))];
+const CLASSIC_COMBINED: [(f64, Color); 2] = [(0.0, Color::rgba(255, 160, 0, 0.0)), (1.0, Color::rgba(255, 160, 0, 1.0))];
+
+/// ユーザー定義のグラデーションの1点
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ColorStop {
+    // 強度上の位置（0.0〜1.0）
+    pub position: f64,
+    // "#rrggbb" または "#rrggbbaa"
+    pub color: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "name", rename_all = "camelCase")]
+pub enum Palette {
+    // Bidは緑、Askは赤の不透明度
+    #[default]
+    Classic,
+    Viridis,
+    Inferno,
+    Bookmap,
+    Custom { stops: Vec<ColorStop> },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorMap {
+    pub palette: Palette,
+    // true ならBid/Askを区別せず、同じグラデーションで塗る
+    pub combined: bool,
+}
+
+/// 強度から色を引く表
+#[derive(Debug, Clone)]
+pub struct Ramp(Vec<Color>);
+
+impl Ramp {
+    fn new(stops: &[(f64, Color)]) -> Self {
+        Self((0..RAMP_STEPS).map(|i| interpolate(stops, i as f64 / (RAMP_STEPS - 1) as f64)).collect())
+    }
+
+    pub fn color(&self, intensity: f64) -> Color {
+        self.0[(intensity.clamp(0.0, 1.0) * (RAMP_STEPS - 1) as f64).round() as usize]
+    }
+
+    /// フロントエンドで使うRGBA（各8bit）の並び
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|c| [c.r, c.g, c.b, (c.a * 255.0).round() as u8]).collect()
+    }
+}
+
+/// Bid側とAsk側の表（Bid/Askを区別しない場合は同じもの）
+#[derive(Debug, Clone)]
+pub struct Ramps {
+    pub bid: Ramp,
+    pub ask: Ramp,
+}
+
+/// 位置で並んだ色の間を線形補間する（範囲外は端の色）
+fn interpolate(stops: &[(f64, Color)], t: f64) -> Color {
+    let Some(upper) = stops.iter().position(|(position, _)| *position >= t) else {
+        return stops[stops.len() - 1].1;
+    };
+    if upper == 0 {
+        return stops[0].1;
+    }
+    let ((p0, c0), (p1, c1)) = (stops[upper - 1], stops[upper]);
+    let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+    let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+    Color::rgba(channel(c0.r, c1.r), channel(c0.g, c1.g), channel(c0.b, c1.b), c0.a + (c1.a - c0.a) * f)
+}
+
+fn parse_stops(stops: &[ColorStop]) -> Result<Vec<(f64, Color)>, String> {
+    if stops.len() < 2 || stops.len() > MAX_STOPS {
+        return Err(format!("a gradient needs 2 to {} stops: {}", MAX_STOPS, stops.len()));
+    }
+    let mut parsed = Vec::with_capacity(stops.len());
+    for stop in stops {
+        if !(0.0..=1.0).contains(&stop.position) {
+            return Err(format!("stop position must be in [0, 1]: {}", stop.position));
+        }
+        if parsed.last().is_some_and(|(previous, _)| *previous > stop.position) {
+            return Err("stops must be sorted by position".to_string());
+        }
+        let color = Color::from_hex(&stop.color).ok_or_else(|| format!("invalid color: {}", stop.color))?;
+        parsed.push((stop.position, color));
+    }
+    Ok(parsed)
+}
+
+impl ColorMap {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Palette::Custom { stops } = &self.palette {
+            parse_stops(stops)?;
+        }
+        Ok(())
+    }
+
+    pub fn ramps(&self) -> Ramps {
+        let sided = |bid: &[(f64, Color)], ask: &[(f64, Color)]| Ramps { bid: Ramp::new(bid), ask: Ramp::new(ask) };
+        let single = |stops: &[(f64, Color)]| {
+            let ramp = Ramp::new(stops);
+            Ramps { bid: ramp.clone(), ask: ramp }
+        };
+        match &self.palette {
+            Palette::Classic if self.combined => single(&CLASSIC_COMBINED),
+            Palette::Classic => sided(&CLASSIC_BID, &CLASSIC_ASK),
+            Palette::Viridis => single(&VIRIDIS),
+            Palette::Inferno => single(&INFERNO),
+            Palette::Bookmap => single(&BOOKMAP),
+            // validate 済みのはずだが、壊れていれば従来の配色にする
+            Palette::Custom { stops } => match parse_stops(stops) {
+                Ok(stops) => single(&stops),
+                Err(_) => sided(&CLASSIC_BID, &CLASSIC_ASK),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(position: f64, color: &str) -> ColorStop {
+        ColorStop { position, color: color.to_string() }
+    }
+
+    #[test]
+    fn ramp_interpolates_between_stops() {
+        let ramp = Ramp::new(&[(0.0, Color::rgba(0, 0, 0, 0.0)), (1.0, Color::rgba(255, 100, 0, 1.0))]);
+        assert_eq!(ramp.color(0.0), Color::rgba(0, 0, 0, 0.0));
+        assert_eq!(ramp.color(1.0), Color::rgba(255, 100, 0, 1.0));
+        assert_eq!(ramp.color(2.0), Color::rgba(255, 100, 0, 1.0));
+        let middle = ramp.color(0.5);
+        assert_eq!((middle.r, middle.g), (128, 50));
+        assert_eq!(ramp.to_rgba().len(), RAMP_STEPS * 4);
+    }
+
+    #[test]
+    fn classic_keeps_sides_unless_combined() {
+        let mut colors = ColorMap::default();
+        let ramps = colors.ramps();
+        let bid = ramps.bid.color(0.5);
+        assert_eq!((bid.r, bid.g, bid.b), (0, 255, 0));
+        assert!((bid.a - 0.5).abs() < 0.01);
+        assert_eq!(ramps.ask.color(1.0), Color::rgba(255, 0, 0, 1.0));
+
+        colors.combined = true;
+        let ramps = colors.ramps();
+        assert_eq!(ramps.bid.color(1.0), ramps.ask.color(1.0));
+    }
+
+    #[test]
+    fn custom_gradient_is_validated() {
+        let custom = |stops| ColorMap { palette: Palette::Custom { stops }, combined: false };
+        let ok = custom(vec![stop(0.0, "#000000"), stop(0.5, "#0000ff80"), stop(1.0, "#ffffff")]);
+        assert!(ok.validate().is_ok());
+        assert_eq!(ok.ramps().ask.color(1.0), Color::rgba(255, 255, 255, 1.0));
+        assert_eq!(Color::from_hex("#0000ff80"), Some(Color::rgba(0, 0, 255, 128.0 / 255.0)));
+
+        assert!(custom(vec![stop(0.0, "#000000")]).validate().is_err());
+        assert!(custom(vec![stop(0.5, "#000000"), stop(0.2, "#ffffff")]).validate().is_err());
+        assert!(custom(vec![stop(0.0, "black"), stop(1.0, "#ffffff")]).validate().is_err());
+        assert!(custom(vec![stop(0.0, "#000000"), stop(1.5, "#ffffff")]).validate().is_err());
+    }
+}
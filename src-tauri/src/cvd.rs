@@ -0,0 +1,82 @@
+// 累積出来高デルタ（CVD）のサブチャート
+use svg::node::element::{Group, Line, Polyline, Rectangle, Text};
+use svg::node::Text as TextNode;
+
+/// ヒートマップと同じ時間軸で、(時刻, CVD) の系列を折れ線で描画する
+/// since〜since + window_ms を x〜x + width に、値の範囲を y〜y + height に対応させる
+/// 折れ線もラベルも、系列の最初の時点からの変化で表す（ゼロラインは最初の時点の水準）
+pub fn render_cvd(series: &[(i64, f64)], since: i64, window_ms: i64, (x, width): (i32, i32), (y, height): (i32, i32)) -> Group {
+    let mut group = Group::new()
+        .add(Rectangle::new()
+            .set("x", x)
+            .set("y", y)
+            .set("width", width)
+            .set("height", height)
+            .set("fill", "#101010"))
+        .add(Line::new()
+            .set("x1", x)
+            .set("x2", x + width)
+            .set("y1", y)
+            .set("y2", y)
+            .set("stroke", "rgba(255, 255, 255, 0.3)")
+            .set("stroke-width", 1));
+
+    let Some(&(_, first)) = series.first() else {
+        return group;
+    };
+    let series: Vec<(i64, f64)> = series.iter().map(|&(time, value)| (time, value - first)).collect();
+    let last = series.last().map_or(0.0, |&(_, value)| value);
+    let (min, max) = series.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &(_, v)| (min.min(v), max.max(v)));
+    // 値が一定の場合も潰れないよう幅を持たせる
+    let padding = ((max - min) * 0.1).max(f64::EPSILON.max(max.abs() * 1e-6));
+    let (min, max) = (min - padding, max + padding);
+    let y_of = |value: f64| y as f64 + height as f64 - (value - min) / (max - min) * height as f64;
+    let x_of = |time: i64| x as f64 + (time - since) as f64 / window_ms as f64 * width as f64;
+
+    // ゼロラインが範囲内にあれば描画
+    if min < 0.0 && max > 0.0 {
+        group = group.add(Line::new()
+            .set("x1", x)
+            .set("x2", x + width)
+            .set("y1", y_of(0.0))
+            .set("y2", y_of(0.0))
+            .set("stroke", "rgba(255, 255, 255, 0.2)")
+            .set("stroke-dasharray", "4 4"));
+    }
+
+    let points: Vec<String> = series
+        .iter()
+        .map(|&(time, value)| format!("{:.1},{:.1}", x_of(time), y_of(value)))
+        .collect();
+    let color = if last >= 0.0 { "rgb(0, 255, 128)" } else { "rgb(255, 64, 64)" };
+    group
+        .add(Polyline::new()
+            .set("points", points.join(" "))
+            .set("fill", "none")
+            .set("stroke", color)
+            .set("stroke-width", 1.5))
+        .add(Text::new()
+            .set("x", x + 8)
+            .set("y", y + 16)
+            .set("font-family", "Arial")
+            .set("font-size", "12")
+            .set("fill", color)
+            .add(TextNode::new(format!("CVD {:+.2}", last))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plots_change_since_window_start() {
+        let series = [(0, 10.0), (500, 12.0), (1_000, 7.0)];
+        let svg = render_cvd(&series, 0, 1_000, (0, 100), (0, 100)).to_string();
+        assert!(svg.contains("CVD -3.00"));
+        // 最初の時点の水準（変化 0）を挟むのでゼロラインを引く
+        assert!(svg.contains("stroke-dasharray"));
+        // 変化は -3〜+2 で、上下に幅の1割ずつ余白を取った -3.5〜+2.5 を高さ100に対応させる
+        // 最初の点（変化 0）は 100 - 3.5 / 6 * 100 の高さ
+        assert!(svg.contains("points=\"0.0,41.7 "));
+    }
+}
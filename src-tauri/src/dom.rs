@@ -0,0 +1,144 @@
+// 現在の板の板寄せ表示（DOMラダー）
+use crate::book::{Level, OrderBookState};
+use ordered_float::OrderedFloat;
+use svg::node::element::{Group, Rectangle, Text};
+use svg::node::Text as TextNode;
+
+const MIN_ROW_HEIGHT: f64 = 2.0;
+const MAX_ROW_HEIGHT: f64 = 16.0;
+const MIN_TEXT_ROW_HEIGHT: f64 = 10.0;  // これより行が低い場合は数量を表示しない
+
+/// 数量を桁に応じた精度で表示する
+fn format_size(size: f64) -> String {
+    if size >= 1000.0 {
+        format!("{:.0}", size)
+    } else if size >= 1.0 {
+        format!("{:.2}", size)
+    } else {
+        format!("{:.4}", size)
+    }
+}
+
+/// 最良気配から遠ざかる方向に累積した数量
+fn cumulative<'a>(levels: impl Iterator<Item = (&'a OrderedFloat<f64>, &'a Level)>) -> Vec<(f64, Level, f64)> {
+    let mut total = 0.0;
+    levels
+        .map(|(price, level)| {
+            total += level.size;
+            (price.into_inner(), *level, total)
+        })
+        .collect()
+}
+
+/// 現在の板を、ヒートマップと同じ価格軸で x から幅 width の領域に描画する
+pub fn render_dom(state: &OrderBookState, (min_price, max_price): (f64, f64), x: i32, width: i32, height: i32) -> Group {
+    let mut group = Group::new()
+        .set("font-family", "Arial")
+        .set("font-size", "11");
+    let price_range = max_price - min_price;
+    if price_range <= 0.0 {
+        return group;
+    }
+    let y_of = |price: f64| height as f64 - (price - min_price) / price_range * height as f64;
+
+    // Bidは高い順、Askは安い順に累積する
+    let bids = cumulative(state.buy.iter().rev());
+    let asks = cumulative(state.sell.iter());
+
+    let max_size = bids.iter().chain(asks.iter()).map(|(_, level, _)| level.size).fold(0.0f64, f64::max);
+    let max_total = bids.iter().chain(asks.iter()).map(|(_, _, total)| *total).fold(0.0f64, f64::max);
+    if max_size <= 0.0 {
+        return group;
+    }
+
+    // 行の高さは隣り合う価格レベルの間隔に合わせる
+    let mut prices: Vec<f64> = state.buy.keys().chain(state.sell.keys()).map(|p| p.into_inner()).collect();
+    prices.sort_by(f64::total_cmp);
+    let min_gap = prices.windows(2).map(|w| (w[1] - w[0]) / price_range * height as f64).fold(f64::INFINITY, f64::min);
+    let row_height = min_gap.clamp(MIN_ROW_HEIGHT, MAX_ROW_HEIGHT);
+
+    let sides = [
+        (&bids, "0, 255, 0", "rgb(160, 255, 160)"),
+        (&asks, "255, 0, 0", "rgb(255, 160, 160)"),
+    ];
+    for (levels, color, text_color) in sides {
+        for (i, &(price, level, total)) in levels.iter().enumerate() {
+            if price < min_price || price > max_price {
+                continue;
+            }
+            let y = y_of(price) - row_height / 2.0;
+            // 先頭が最良気配
+            let best = i == 0;
+
+            // 累積数量（薄い帯）
+            group = group.add(Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", total / max_total * width as f64)
+                .set("height", row_height)
+                .set("fill", format!("rgba({}, 0.15)", color)));
+
+            // その価格の数量
+            let mut bar = Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", level.size / max_size * width as f64)
+                .set("height", row_height)
+                .set("fill", format!("rgba({}, {})", color, if best { 0.9 } else { 0.5 }));
+            if best {
+                bar = bar.set("stroke", "yellow").set("stroke-width", 1);
+            }
+            group = group.add(bar);
+
+            if row_height >= MIN_TEXT_ROW_HEIGHT {
+                let mut text = Text::new()
+                    .set("x", x + 4)
+                    .set("y", y + row_height - 2.0)
+                    .set("fill", if best { "yellow" } else { text_color })
+                    .add(TextNode::new(format!(
+                        "{} ({}) Σ{}",
+                        format_size(level.size),
+                        level.orders,
+                        format_size(total)
+                    )));
+                if best {
+                    text = text.set("font-weight", "bold");
+                }
+                group = group.add(text);
+            }
+        }
+    }
+
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::BookSide;
+    use crate::testing::{book, side};
+
+    fn totals(levels: &[(f64, Level, f64)]) -> Vec<(f64, f64)> {
+        levels.iter().map(|(price, _, total)| (*price, *total)).collect()
+    }
+
+    #[test]
+    fn accumulates_away_from_best_price() {
+        let buy = side(&[(97.0, 4.0, 1), (98.0, 2.0, 1), (99.0, 1.0, 1)]);
+        let sell = side(&[(101.0, 3.0, 1), (102.0, 5.0, 1)]);
+        // Bidは最良気配（最も高い価格）から下へ、Askは最も安い価格から上へ
+        assert_eq!(totals(&cumulative(buy.iter().rev())), [(99.0, 1.0), (98.0, 3.0), (97.0, 7.0)]);
+        assert_eq!(totals(&cumulative(sell.iter())), [(101.0, 3.0), (102.0, 8.0)]);
+        assert!(cumulative(BookSide::new().iter()).is_empty());
+    }
+
+    #[test]
+    fn highlights_only_best_levels() {
+        let state = book(&[(98.0, 2.0, 1), (99.0, 1.0, 1)], &[(101.0, 3.0, 1), (102.0, 5.0, 1)]);
+        let svg = render_dom(&state, (90.0, 110.0), 0, 200, 400).to_string();
+        // 最良気配の2行だけを黄色で縁取る
+        assert_eq!(svg.matches("stroke=\"yellow\"").count(), 2);
+        // 価格範囲がなければ何も描かない
+        assert_eq!(render_dom(&state, (100.0, 100.0), 0, 200, 400).to_string().matches("<rect").count(), 0);
+    }
+}
@@ -0,0 +1,289 @@
+// Hyperliquid WebSocket APIとの接続
+// l2Bookのメッセージには nSigFigs が含まれないため、集約レベルごとに接続を分け、
+// 1本の接続にはその集約で購読する全銘柄を多重化する
+// 銘柄がいくつあっても接続数は集約の種類（nSigFigs 2〜5・集約なし・mantissa 2/5 の7種類）までで、
+// 自動選択の銘柄だけなら AUTO_AGGREGATIONS の4本になる。接続を1本にまとめられない代わりに、
+// 集約の異なる板が同じ履歴に混ざることがない
+use crate::book::{self, BookMap};
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+
+const WS_URL: &str = "wss://api.hyperliquid.xyz/ws";
+
+#[derive(Debug, Deserialize)]
+pub struct WsLevel {
+    pub px: String,
+    pub sz: String,
+    pub n: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsBook {
+    pub coin: String,
+    pub levels: Vec<Vec<WsLevel>>,
+    pub time: i64,
+}
+
+/// 約定の方向（テイカー側: B は買い、A は売り）
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    #[serde(rename = "B")]
+    Buy,
+    #[serde(rename = "A")]
+    Sell,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsTrade {
+    pub coin: String,
+    pub side: Side,
+    pub px: String,
+    pub sz: String,
+    pub time: i64,
+    pub tid: u64,
+    // [買い手, 売り手] のアドレス（現在は描画に使っていない。含まれないメッセージもある）
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub users: Option<[String; 2]>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "channel", content = "data", rename_all = "camelCase")]
+pub enum WsMessage {
+    L2Book(WsBook),
+    Trades(Vec<WsTrade>),
+    SubscriptionResponse(serde_json::Value),
+}
+
+/// 1本の接続で購読するチャンネル
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subscription {
+    L2Book(String),
+    Trades(String),
+}
+
+impl Subscription {
+    fn request(&self, method: &str, aggregation: Aggregation) -> Message {
+        let subscription = match self {
+            Self::L2Book(coin) => {
+                let mut subscription = serde_json::json!({
+                    "type": "l2Book",
+                    "coin": coin,
+                    "nSigFigs": aggregation.n_sig_figs
+                });
+                if let Some(mantissa) = aggregation.mantissa {
+                    subscription["mantissa"] = mantissa.into();
+                }
+                subscription
+            }
+            Self::Trades(coin) => serde_json::json!({
+                "type": "trades",
+                "coin": coin
+            }),
+        };
+        let msg = serde_json::json!({
+            "method": method,
+            "subscription": subscription
+        });
+        Message::Text(msg.to_string())
+    }
+}
+
+/// l2Bookの価格集約（nSigFigs: 2〜5、None は集約なし。mantissa は nSigFigs = 5 のときのみ 1, 2, 5）
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct Aggregation {
+    pub n_sig_figs: Option<u8>,
+    pub mantissa: Option<u8>,
+}
+
+impl Default for Aggregation {
+    fn default() -> Self {
+        Self::sig_figs(5)
+    }
+}
+
+impl Aggregation {
+    pub const fn sig_figs(n: u8) -> Self {
+        Self {
+            n_sig_figs: Some(n),
+            mantissa: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(n) = self.n_sig_figs {
+            if !(2..=5).contains(&n) {
+                return Err(format!("nSigFigs must be between 2 and 5: {}", n));
+            }
+        }
+        if let Some(m) = self.mantissa {
+            if self.n_sig_figs != Some(5) {
+                return Err("mantissa is only allowed when nSigFigs is 5".to_string());
+            }
+            // mantissa 1 は指定なしと同じ板になり、同じ内容の接続が増えるだけなので受け付けない
+            if !matches!(m, 2 | 5) {
+                return Err(format!("mantissa must be 2 or 5: {}", m));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 購読内容の変化に合わせて、集約レベルごとの接続タスクを起動・停止する
+pub async fn run_feeds(
+    books: Arc<RwLock<BookMap>>,
+    mut changes: watch::Receiver<()>,
+    tx: mpsc::Sender<(Aggregation, Instant, WsMessage)>,
+) {
+    let mut connections: BTreeMap<Aggregation, JoinHandle<()>> = BTreeMap::new();
+    loop {
+        let needed: BTreeSet<Aggregation> = book::subscriptions(&books).into_keys().collect();
+
+        // 使われなくなった集約の接続は閉じる（購読も接続ごと消える）
+        connections.retain(|aggregation, handle| {
+            if needed.contains(aggregation) {
+                return true;
+            }
+            info!("Closing WebSocket connection for {:?}", aggregation);
+            handle.abort();
+            false
+        });
+
+        for aggregation in needed {
+            connections.entry(aggregation).or_insert_with(|| {
+                tokio::spawn(run_connection(aggregation, books.clone(), changes.clone(), tx.clone()))
+            });
+        }
+
+        if changes.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+/// 1つの集約レベルの接続を維持し、その接続で購読すべきチャンネルを同期する
+async fn run_connection(
+    aggregation: Aggregation,
+    books: Arc<RwLock<BookMap>>,
+    mut changes: watch::Receiver<()>,
+    tx: mpsc::Sender<(Aggregation, Instant, WsMessage)>,
+) {
+    let url = Url::parse(WS_URL).unwrap();
+    let desired_subscriptions = || book::subscriptions(&books).remove(&aggregation).unwrap_or_default();
+
+    let mut retry_count = 0;
+    loop {
+        info!("Connecting to WebSocket with {:?} (attempt: {})", aggregation, retry_count + 1);
+
+        // 指数バックオフによる再試行待機
+        if retry_count > 0 {
+            let wait_time = std::cmp::min(1 << retry_count, 30); // 最大30秒
+            tokio::time::sleep(tokio::time::Duration::from_secs(wait_time)).await;
+        }
+
+        match connect_async(url.clone()).await {
+            Ok((mut ws_stream, _)) => {
+                info!("WebSocket connected");
+                // 1本の接続に全銘柄の購読を多重化する
+                let mut subscribed = BTreeSet::new();
+                let mut result = Ok(());
+                for subscription in desired_subscriptions() {
+                    result = ws_stream.send(subscription.request("subscribe", aggregation)).await;
+                    if result.is_err() {
+                        break;
+                    }
+                    subscribed.insert(subscription);
+                }
+
+                match result {
+                    Ok(_) => {
+                        info!("Subscription messages sent for {:?}: {:?}", aggregation, subscribed);
+                        retry_count = 0; // 接続成功したらリトライカウントをリセット
+
+                        loop {
+                            tokio::select! {
+                                msg = ws_stream.next() => {
+                                    let Some(msg) = msg else { break };
+                                    match msg {
+                                        Ok(msg) => {
+                                            match serde_json::from_str::<WsMessage>(&msg.to_string()) {
+                                                Ok(parsed) => {
+                                                    // 受信時刻を付けて、集約の変更前に受信した板を処理側で捨てられるようにする
+                                                    if let Err(e) = tx.send((aggregation, Instant::now(), parsed)).await {
+                                                        error!("Failed to send message through channel: {:?}", e);
+                                                        break;
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    warn!("Failed to parse WebSocket message: {:?}", e);
+                                                    warn!("Message content: {}", msg.to_string());
+                                                }
+                                            }
+                                        }
+                                        Err(e) => {
+                                            error!("WebSocket error: {:?}", e);
+                                            break;
+                                        }
+                                    }
+                                }
+                                _ = changes.changed() => {
+                                    // 銘柄の変更: 不要になった購読を解除してから新しい購読を追加
+                                    let desired = desired_subscriptions();
+                                    let removed: Vec<Subscription> = subscribed.difference(&desired).cloned().collect();
+                                    let added: Vec<Subscription> = desired.difference(&subscribed).cloned().collect();
+                                    let mut failed = false;
+                                    for subscription in removed {
+                                        info!("Unsubscribing {:?} {:?}", subscription, aggregation);
+                                        if let Err(e) = ws_stream.send(subscription.request("unsubscribe", aggregation)).await {
+                                            error!("Failed to send unsubscribe message: {:?}", e);
+                                            failed = true;
+                                            break;
+                                        }
+                                        subscribed.remove(&subscription);
+                                    }
+                                    for subscription in added {
+                                        if failed {
+                                            break;
+                                        }
+                                        info!("Subscribing {:?} {:?}", subscription, aggregation);
+                                        if let Err(e) = ws_stream.send(subscription.request("subscribe", aggregation)).await {
+                                            error!("Failed to send subscription message: {:?}", e);
+                                            failed = true;
+                                            break;
+                                        }
+                                        subscribed.insert(subscription);
+                                    }
+                                    if failed {
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to send subscription message: {:?}", e);
+                        retry_count += 1;
+                        continue;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to connect WebSocket: {:?}", e);
+                retry_count += 1;
+            }
+        }
+
+        info!("WebSocket connection lost. Reconnecting...");
+    }
+}
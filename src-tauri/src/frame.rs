@@ -0,0 +1,96 @@
+// 受信とは独立したフレームクロックで描画するための状態
+// 受信した更新は銘柄ごとに印を付けておくだけにして、次のフレームでまとめて1回描画する
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+pub const DEFAULT_FRAME_RATE: u32 = 10;
+const MIN_FRAME_RATE: u32 = 1;
+const MAX_FRAME_RATE: u32 = 30;
+
+pub fn validate_frame_rate(fps: u32) -> Result<(), String> {
+    if !(MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&fps) {
+        return Err(format!("frame rate must be between {} and {}: {}", MIN_FRAME_RATE, MAX_FRAME_RATE, fps));
+    }
+    Ok(())
+}
+
+pub fn frame_period(fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / fps as f64)
+}
+
+/// 次のフレームで描画する銘柄と、それまでに受信した更新の数
+#[derive(Default)]
+pub struct PendingFrames {
+    coins: BTreeMap<String, u32>,
+}
+
+impl PendingFrames {
+    pub fn mark(&mut self, coin: &str) {
+        *self.coins.entry(coin.to_string()).or_default() += 1;
+    }
+
+    pub fn take(&mut self) -> BTreeMap<String, u32> {
+        std::mem::take(&mut self.coins)
+    }
+}
+
+/// 描画の統計（フロントエンドへ返す）
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameStats {
+    pub fps: u32,
+    // 描画したフレーム数
+    pub rendered: u64,
+    // 描画が間に合わずに飛ばしたフレーム数
+    pub dropped: u64,
+    // 1フレームにまとめたことで描画しなかった更新の数
+    pub coalesced: u64,
+    pub last_render_ms: f64,
+    pub max_render_ms: f64,
+}
+
+impl FrameStats {
+    /// 1フレーム分の描画を記録し、飛ばしたフレーム数を返す
+    pub fn record(&mut self, pending: &BTreeMap<String, u32>, elapsed: Duration, period: Duration) -> u64 {
+        let dropped = (elapsed.as_secs_f64() / period.as_secs_f64()).floor() as u64;
+        let render_ms = elapsed.as_secs_f64() * 1000.0;
+        self.rendered += 1;
+        self.dropped += dropped;
+        self.coalesced += pending.values().map(|updates| updates.saturating_sub(1) as u64).sum::<u64>();
+        self.last_render_ms = render_ms;
+        self.max_render_ms = self.max_render_ms.max(render_ms);
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_frames_count_updates_per_coin() {
+        let mut pending = PendingFrames::default();
+        for coin in ["BTC", "ETH", "BTC", "BTC"] {
+            pending.mark(coin);
+        }
+        assert_eq!(pending.take(), BTreeMap::from([("BTC".to_string(), 3), ("ETH".to_string(), 1)]));
+        assert!(pending.take().is_empty());
+    }
+
+    #[test]
+    fn record_counts_coalesced_updates_and_dropped_frames() {
+        let mut stats = FrameStats::default();
+        let period = frame_period(10);
+        let pending = BTreeMap::from([("BTC".to_string(), 3), ("ETH".to_string(), 1)]);
+
+        // 2.5フレーム分かかったので2フレーム飛ばし、BTCの3回の更新は1回の描画にまとめた
+        assert_eq!(stats.record(&pending, Duration::from_millis(250), period), 2);
+        assert_eq!((stats.rendered, stats.dropped, stats.coalesced), (1, 2, 2));
+
+        // 間に合ったフレームは飛ばさず、最大の描画時間は残る
+        assert_eq!(stats.record(&pending, Duration::from_millis(50), period), 0);
+        assert_eq!((stats.rendered, stats.dropped, stats.coalesced), (2, 2, 4));
+        assert_eq!((stats.last_render_ms, stats.max_render_ms), (50.0, 250.0));
+    }
+}
@@ -0,0 +1,188 @@
+// フロントエンドで描画するための時間×価格の数値グリッド
+use crate::book::{HeatmapMetric, OrderBookState};
+use crate::colormap::ColorMap;
+use crate::feed::Aggregation;
+use crate::heatmap::RenderStyle;
+use crate::intensity::IntensityScale;
+use crate::viewport::ViewRange;
+use base64::Engine;
+use serde::Serialize;
+
+const MAX_ROWS: usize = 2000;  // 価格方向の最大行数（これを超える場合は行の価格幅を広げる）
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatmapGrid {
+    pub coin: String,
+    pub aggregation: Aggregation,
+    pub metric: HeatmapMetric,
+    // 表示する時間範囲（ミリ秒）。列 i は times[i] から次の列の時刻まで（最後の列は until まで）の板を表す
+    // 最初の列は since より前から続く板なので、since より前の時刻のことがある
+    pub since: i64,
+    pub until: i64,
+    // 時間軸: 各列のスナップショット時刻（ミリ秒）
+    pub times: Vec<i64>,
+    // 価格軸: 行 r の価格は min_price + r * price_step（下から上へ）
+    pub min_price: f64,
+    pub price_step: f64,
+    pub rows: usize,
+    // 列ごとに rows 個並べた強度（-1.0〜1.0 の f32リトルエンディアンをbase64にしたもの）
+    // 正の値はBid、負の値はAsk、0.0 はその価格に板がないことを表す
+    // Bid/Askを区別しない配色では、両側を合算した 0.0〜1.0 の値になる
+    pub values: String,
+    pub scale: IntensityScale,
+    // 強度 1.0 に対応する指標の値（板がなければ null）
+    pub reference: Option<f64>,
+    pub colors: ColorMap,
+    // 強度 0.0〜1.0 を256段階に分けた色（RGBA各8bitをbase64にしたもの）。正の値と負の値で使い分ける
+    pub positive_ramp: String,
+    pub negative_ramp: String,
+    // 各列の最良気配とMid価格（板が片側しかない時刻は null）
+    pub best_bid: Vec<Option<f64>>,
+    pub best_ask: Vec<Option<f64>>,
+    pub mid: Vec<Option<f64>>,
+}
+
+impl HeatmapGrid {
+    /// 表示範囲内の履歴をグリッドにする（履歴がなければ None）
+    pub fn build(
+        coin: &str,
+        aggregation: Aggregation,
+        state: &OrderBookState,
+        style: &RenderStyle,
+        view: ViewRange,
+    ) -> Option<Self> {
+        let RenderStyle { metric, scale, colors, .. } = *style;
+        let ViewRange { since, until, min_price, max_price } = view;
+        let history = state.history_between((since, until));
+        if history.is_empty() || max_price <= min_price {
+            return None;
+        }
+        // 行の価格幅は板の価格間隔に合わせ、行数が多すぎる場合だけ粗くする
+        let span = max_price - min_price;
+        let price_step = state.price_gap().unwrap_or(span).max(span / (MAX_ROWS - 1) as f64);
+        let rows = (span / price_step).round() as usize + 1;
+
+        let columns = history.len();
+        let mut values = vec![0.0f32; columns * rows];
+        let (mut best_bid, mut best_ask, mut mid) = (
+            Vec::with_capacity(columns),
+            Vec::with_capacity(columns),
+            Vec::with_capacity(columns),
+        );
+
+        for (column, (_, buy, sell)) in history.iter().enumerate() {
+            let cells = &mut values[column * rows..(column + 1) * rows];
+            let ask_sign = if colors.combined { 1.0 } else { -1.0 };
+            for (levels, sign) in [(buy, 1.0), (sell, ask_sign)] {
+                for (price, level) in levels.iter() {
+                    let price = price.into_inner();
+                    if price < min_price || price > max_price {
+                        continue;
+                    }
+                    let row = (((price - min_price) / price_step).round() as usize).min(rows - 1);
+                    cells[row] += (sign * metric.value(level)) as f32;
+                }
+            }
+
+            let bid = buy.keys().next_back().map(|p| p.into_inner());
+            let ask = sell.keys().next().map(|p| p.into_inner());
+            best_bid.push(bid);
+            best_ask.push(ask);
+            mid.push(bid.zip(ask).map(|(bid, ask)| (bid + ask) / 2.0));
+        }
+
+        // 同じ行にまとめた価格は合算されるので、基準値は合算後の値から求める
+        let reference = scale.reference(values.iter().map(|v| v.abs() as f64));
+        if let Some(reference) = reference {
+            for value in values.iter_mut() {
+                *value = value.signum() * scale.intensity(value.abs() as f64, reference) as f32;
+            }
+        }
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let ramps = colors.ramps();
+        let encode = |bytes: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(bytes);
+        Some(Self {
+            coin: coin.to_string(),
+            aggregation,
+            metric,
+            since,
+            until,
+            times: history.iter().map(|(ts, _, _)| *ts).collect(),
+            min_price,
+            price_step,
+            rows,
+            values: encode(bytes),
+            scale,
+            reference,
+            colors: colors.clone(),
+            positive_ramp: encode(ramps.bid.to_rgba()),
+            negative_ramp: encode(ramps.ask.to_rgba()),
+            best_bid,
+            best_ask,
+            mid,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::book;
+
+    fn state(buy: &[(f64, f64, i32)], sell: &[(f64, f64, i32)]) -> OrderBookState {
+        let mut state = book(buy, sell);
+        state.update_history(1_000);
+        state
+    }
+
+    fn build(state: &OrderBookState, (min_price, max_price): (f64, f64)) -> HeatmapGrid {
+        let colors = ColorMap::default();
+        let style = RenderStyle {
+            metric: HeatmapMetric::Size,
+            scale: IntensityScale::default(),
+            colors: &colors,
+            time_axis: Default::default(),
+            tick_size: None,
+        };
+        let view = ViewRange { since: 0, until: 1_000, min_price, max_price };
+        HeatmapGrid::build("BTC", Aggregation::default(), state, &style, view).unwrap()
+    }
+
+    fn decode(values: &str) -> Vec<f32> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(values).unwrap();
+        bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+    }
+
+    #[test]
+    fn values_round_trip_as_little_endian_f32() {
+        // 価格間隔1.0なので 95〜105 は11行、Askは負の値になる
+        let state = state(&[(98.0, 1.0, 1), (99.0, 2.0, 1)], &[(101.0, 4.0, 1)]);
+        let grid = build(&state, (95.0, 105.0));
+        assert_eq!((grid.since, grid.until, grid.times.clone()), (0, 1_000, vec![1_000]));
+        assert_eq!((grid.price_step, grid.rows), (1.0, 11));
+        assert_eq!(grid.reference, Some(4.0));
+
+        let mut expected = vec![0.0f32; 11];
+        expected[3] = 0.25;
+        expected[4] = 0.5;
+        expected[6] = -1.0;
+        assert_eq!(decode(&grid.values), expected);
+        assert_eq!((grid.best_bid[0], grid.best_ask[0], grid.mid[0]), (Some(99.0), Some(101.0), Some(100.0)));
+    }
+
+    #[test]
+    fn caps_rows_by_widening_price_step() {
+        // 価格間隔0.4で 0〜10000 を刻むと2万行を超えるので、MAX_ROWS 行に収まるよう行の価格幅を広げる
+        let state = state(&[(5_002.3, 3.0, 1)], &[(5_002.7, 1.0, 1)]);
+        let grid = build(&state, (0.0, 10_000.0));
+        assert_eq!(grid.rows, MAX_ROWS);
+        assert_eq!(grid.price_step, 10_000.0 / (MAX_ROWS - 1) as f64);
+
+        // 同じ行にまとめたBidとAskは合算される
+        let mut expected = vec![0.0f32; MAX_ROWS];
+        expected[1_000] = 1.0;
+        assert_eq!(decode(&grid.values), expected);
+        assert_eq!(grid.reference, Some(2.0));
+    }
+}
@@ -0,0 +1,619 @@
+// ヒートマップの描画
+// 配信用には本体（板の濃淡・Midライン・約定）をピクセルバッファに描いてPNGにし、
+// 文字を含む補助表示だけをSVGで重ねる。全体をSVGで描く経路はエクスポート用に残す
+use crate::axis::{PriceTicks, TimeAxis};
+use crate::book::{HeatmapMetric, Level, OrderBookState, TradeWindow};
+use crate::colormap::{ColorMap, Ramp, Ramps};
+use crate::cvd::render_cvd;
+use crate::dom::render_dom;
+use crate::feed::{Aggregation, Side};
+use crate::history::CompactSide;
+use crate::intensity::IntensityScale;
+use crate::profile::VolumeProfile;
+use crate::raster::{Canvas, Color};
+use crate::viewport::ViewRange;
+use log::{info, warn};
+use ordered_float::OrderedFloat;
+use svg::node::element::{Circle, ClipPath, Definitions, Group, Line, Rectangle, Text};
+use svg::node::Text as TextNode;
+use svg::{Document, Node};
+
+pub const HEATMAP_WIDTH: i32 = 1920;
+pub const HEATMAP_HEIGHT: i32 = 1080;
+const TIME_AXIS_HEIGHT: i32 = 24;  // 最下部の時刻軸の高さ
+const CVD_HEIGHT: i32 = 160;  // 時刻軸の上のCVDチャートの高さ
+const PRICE_AREA_HEIGHT: i32 = HEATMAP_HEIGHT - CVD_HEIGHT - TIME_AXIS_HEIGHT;  // 価格軸を持つ領域の高さ
+const TIME_AXIS_Y: i32 = HEATMAP_HEIGHT - TIME_AXIS_HEIGHT;  // 時刻軸の位置
+const PRICE_TICKS: usize = 10;  // 価格軸の目盛りの数の目安
+const DOM_WIDTH: i32 = 260;  // DOMラダーの幅
+const RIGHT_MARGIN: i32 = 300 + DOM_WIDTH;  // 右側の余白
+const ACTUAL_HEATMAP_WIDTH: i32 = HEATMAP_WIDTH - RIGHT_MARGIN;  // ヒートマップの実際の描画幅
+const PRICE_AXIS_X: i32 = ACTUAL_HEATMAP_WIDTH + DOM_WIDTH;  // 価格軸の位置（DOMラダーの右）
+const PROFILE_X: i32 = PRICE_AXIS_X + 100;  // ボリュームプロファイルの開始位置（価格ラベルの右）
+const PROFILE_WIDTH: i32 = HEATMAP_WIDTH - PROFILE_X - 10;  // ボリュームプロファイルの最大幅
+const MIN_TRADE_RADIUS: f64 = 2.0;  // 約定バブルの最小半径
+const MAX_TRADE_RADIUS: f64 = 24.0;  // 約定バブルの最大半径
+const MAX_TRADE_BUBBLES: usize = 2000;  // 描画する約定の最大数（超える場合はサイズの大きいものだけを描く）
+const REDRAW_TOLERANCE: f64 = 0.05;  // 濃淡の基準値がこの割合以上動いたら全体を描き直す
+const REFERENCE_INTERVAL_MS: i64 = 1_000;  // 描き足している間は、この間隔でだけ濃淡の基準値を求め直す
+
+const BACKGROUND_COLOR: Color = Color::rgba(0, 0, 0, 1.0);
+const MID_COLOR: Color = Color::rgba(255, 255, 255, 0.8);
+const BUY_TRADE_COLOR: Color = Color::rgba(0, 255, 128, 0.5);
+const SELL_TRADE_COLOR: Color = Color::rgba(255, 64, 64, 0.5);
+const TRADE_STROKE_COLOR: Color = Color::rgba(255, 255, 255, 0.8);
+const BODY_CLIP_ID: &str = "heatmap-body";  // エクスポートするSVGで本体を切り取るクリップパス
+
+/// 描画の見た目（濃淡の指標・付け方・配色と軸の表示）
+pub struct RenderStyle<'a> {
+    pub metric: HeatmapMetric,
+    pub scale: IntensityScale,
+    pub colors: &'a ColorMap,
+    pub time_axis: TimeAxis,
+    // 価格の刻み（分からなければ None）
+    pub tick_size: Option<f64>,
+}
+
+/// 描画する時間・価格範囲と座標変換
+struct Frame {
+    since: i64,
+    until: i64,
+    window: i64,
+    min_price: f64,
+    max_price: f64,
+    // 濃淡の付け方と、強度 1.0 に対応する指標の値
+    scale: IntensityScale,
+    reference: f64,
+    ramps: Ramps,
+}
+
+impl Frame {
+    fn new(view: ViewRange, style: &RenderStyle, reference: f64) -> Self {
+        Self {
+            since: view.since,
+            until: view.until,
+            window: (view.until - view.since).max(1),
+            min_price: view.min_price,
+            max_price: view.max_price,
+            scale: style.scale,
+            reference,
+            ramps: style.colors.ramps(),
+        }
+    }
+
+    fn x_of(&self, time: i64) -> f64 {
+        (time - self.since) as f64 / self.window as f64 * ACTUAL_HEATMAP_WIDTH as f64
+    }
+
+    fn y_of(&self, price: f64) -> f64 {
+        PRICE_AREA_HEIGHT as f64 - (price - self.min_price) / (self.max_price - self.min_price) * PRICE_AREA_HEIGHT as f64
+    }
+
+    fn contains(&self, price: f64) -> bool {
+        price >= self.min_price && price <= self.max_price
+    }
+
+    fn intensity(&self, value: f64) -> f64 {
+        self.scale.intensity(value, self.reference)
+    }
+}
+
+/// 表示範囲の履歴の指標の分布から、強度 1.0 に対応する値を求める
+fn reference_of(state: &OrderBookState, range: (i64, i64), metric: HeatmapMetric, scale: IntensityScale) -> Option<f64> {
+    let values = state.history_between(range).iter()
+        .flat_map(|(_, buy, sell)| buy.values().chain(sell.values()))
+        .map(|level| metric.value(level));
+    let Some(reference) = scale.reference(values) else {
+        warn!("No data available for heatmap");
+        return None;
+    };
+    Some(reference)
+}
+
+/// 本体上の位置 (x, y)（viewBox の座標）を時刻と価格に変換する（本体の外なら None）
+pub fn point_at((x, y): (f64, f64), view: ViewRange) -> Option<(i64, f64)> {
+    if !(0.0..=ACTUAL_HEATMAP_WIDTH as f64).contains(&x) || !(0.0..=PRICE_AREA_HEIGHT as f64).contains(&y) {
+        return None;
+    }
+    let time = view.since + ((view.until - view.since) as f64 * x / ACTUAL_HEATMAP_WIDTH as f64).round() as i64;
+    let price = view.max_price - (view.max_price - view.min_price) * y / PRICE_AREA_HEIGHT as f64;
+    Some((time, price))
+}
+
+/// 本体の外の位置は最も近い本体の端の位置として時刻と価格に変換する
+pub fn clamped_point_at((x, y): (f64, f64), view: ViewRange) -> Option<(i64, f64)> {
+    if !(x.is_finite() && y.is_finite()) {
+        return None;
+    }
+    point_at((x.clamp(0.0, ACTUAL_HEATMAP_WIDTH as f64), y.clamp(0.0, PRICE_AREA_HEIGHT as f64)), view)
+}
+
+/// 本体上の移動量 (dx, dy) を時間と価格の移動量に変換する（右・上へ動かすと正）
+pub fn delta_at((dx, dy): (f64, f64), view: ViewRange) -> (i64, f64) {
+    let time = ((view.until - view.since) as f64 * dx / ACTUAL_HEATMAP_WIDTH as f64).round() as i64;
+    let price = -(view.max_price - view.min_price) * dy / PRICE_AREA_HEIGHT as f64;
+    (time, price)
+}
+
+/// 時刻を画素の列番号に変換する（1列 = window / ACTUAL_HEATMAP_WIDTH ミリ秒）
+fn column_of(time: i64, window: i64) -> i64 {
+    (time as f64 / window as f64 * ACTUAL_HEATMAP_WIDTH as f64).floor() as i64
+}
+
+/// ヒートマップ本体の描画先
+/// 円は本体の右端（ACTUAL_HEATMAP_WIDTH）からはみ出した部分を描かない
+trait Painter {
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color);
+    fn circle(&mut self, cx: f64, cy: f64, radius: f64, fill: Color, stroke: Color);
+}
+
+impl Painter for Canvas {
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        Canvas::fill_rect(self, x, y, width, height, color);
+    }
+
+    fn circle(&mut self, cx: f64, cy: f64, radius: f64, fill: Color, stroke: Color) {
+        self.fill_circle(cx, cy, radius, (fill, stroke), ACTUAL_HEATMAP_WIDTH);
+    }
+}
+
+/// 要素ごとにSVGノードを作る描画先（エクスポート用）
+struct SvgPainter(Group);
+
+impl Painter for SvgPainter {
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        self.0.append(Rectangle::new()
+            .set("x", x)
+            .set("y", y)
+            .set("width", width)
+            .set("height", height)
+            .set("fill", color.css()));
+    }
+
+    fn circle(&mut self, cx: f64, cy: f64, radius: f64, fill: Color, stroke: Color) {
+        self.0.append(Circle::new()
+            .set("cx", cx)
+            .set("cy", cy)
+            .set("r", radius)
+            .set("fill", fill.css())
+            .set("stroke", stroke.css())
+            .set("stroke-width", 1)
+            .set("clip-path", format!("url(#{})", BODY_CLIP_ID)));
+    }
+}
+
+/// 1時点の片側の板を、指標の大きさに応じた色で描画する
+fn paint_levels<'a>(
+    painter: &mut impl Painter,
+    levels: impl Iterator<Item = (&'a OrderedFloat<f64>, &'a Level)>,
+    ramp: &Ramp,
+    metric: HeatmapMetric,
+    frame: &Frame,
+    (x, width): (i32, i32),
+) {
+    for (price, level) in levels {
+        let price = price.into_inner();
+        if !frame.contains(price) {
+            continue;
+        }
+        let color = ramp.color(frame.intensity(metric.value(level)));
+        painter.fill_rect(x, frame.y_of(price) as i32, width, 2, color);
+    }
+}
+
+/// 1時点の板とMid価格を x から幅 width の列に描画する
+fn paint_snapshot(painter: &mut impl Painter, buy: &CompactSide, sell: &CompactSide, metric: HeatmapMetric, frame: &Frame, (x, width): (i32, i32)) {
+    paint_levels(painter, sell.iter().rev(), &frame.ramps.ask, metric, frame, (x, width));
+    paint_levels(painter, buy.iter(), &frame.ramps.bid, metric, frame, (x, width));
+
+    // その時点でのMid価格
+    let best_buy = buy.keys().next_back().map(|x| x.into_inner()).unwrap_or(0.0);
+    let best_sell = sell.keys().next().map(|x| x.into_inner()).unwrap_or(0.0);
+    let mid = (best_buy + best_sell) / 2.0;
+    painter.fill_rect(x, frame.y_of(mid) as i32, width, 1, MID_COLOR);
+}
+
+/// 約定をサイズに応じた円で描画する（買い: 緑、売り: 赤）
+fn paint_trades(painter: &mut impl Painter, trades: &TradeWindow, frame: &Frame) {
+    let max_trade_size = trades.iter().map(|t| t.size).fold(0.0f64, f64::max);
+    let mut visible: Vec<_> = trades.iter()
+        .filter(|t| t.time > frame.since && t.time <= frame.until && frame.contains(t.price))
+        .collect();
+    // 時間幅が長いと約定が多すぎるので、大きいものだけを残して時刻順に描く
+    if visible.len() > MAX_TRADE_BUBBLES {
+        visible.select_nth_unstable_by(MAX_TRADE_BUBBLES - 1, |a, b| b.size.total_cmp(&a.size));
+        visible.truncate(MAX_TRADE_BUBBLES);
+        visible.sort_by_key(|t| t.time);
+    }
+    for trade in visible {
+        let radius = MIN_TRADE_RADIUS + (MAX_TRADE_RADIUS - MIN_TRADE_RADIUS) * (trade.size / max_trade_size).sqrt();
+        let color = match trade.side {
+            Side::Buy => BUY_TRADE_COLOR,
+            Side::Sell => SELL_TRADE_COLOR,
+        };
+        painter.circle(frame.x_of(trade.time), frame.y_of(trade.price), radius, color, TRADE_STROKE_COLOR);
+    }
+}
+
+/// 文字や線を含む補助表示（DOMラダー・ボリュームプロファイル・CVD・価格軸・時刻軸）
+fn overlay(state: &OrderBookState, trades: &TradeWindow, frame: &Frame, style: &RenderStyle) -> Group {
+    let (min_price, max_price) = (frame.min_price, frame.max_price);
+
+    // ヒートマップの右に現在の板を描画
+    let mut group = Group::new()
+        .add(render_dom(state, (min_price, max_price), ACTUAL_HEATMAP_WIDTH, DOM_WIDTH, PRICE_AREA_HEIGHT));
+
+    // 右側の余白に価格帯別出来高を描画
+    if let Some(profile) = VolumeProfile::build(state, trades, (frame.since, frame.until), (min_price, max_price)) {
+        group = group.add(profile.render(PROFILE_X, PROFILE_WIDTH, PRICE_AREA_HEIGHT));
+    }
+
+    // 下部に履歴と同じ時刻で累積出来高デルタを描画
+    let cvd: Vec<(i64, f64)> = state.history_between((frame.since, frame.until)).iter().map(|(ts, _, _)| (*ts, trades.cvd_at(*ts))).collect();
+    group = group.add(render_cvd(
+        &cvd,
+        frame.since,
+        frame.window,
+        (0, ACTUAL_HEATMAP_WIDTH),
+        (PRICE_AREA_HEIGHT, CVD_HEIGHT),
+    ));
+
+    // 最下部に時刻軸を描画（補助線はヒートマップとCVDをまたいで引く）
+    group = group.add(style.time_axis.render((frame.since, frame.window), (0, ACTUAL_HEATMAP_WIDTH), (0, TIME_AXIS_Y)));
+
+    // 価格軸のグループを作成
+    let mut price_axis_group = Group::new()
+        .set("font-family", "Arial")
+        .set("font-size", "14")
+        .set("fill", "white");
+
+    // 価格軸の目盛りを生成（価格の刻みに揃えた切りのよい価格）
+    let ticks = PriceTicks::new((min_price, max_price), PRICE_TICKS, style.tick_size);
+    for &price in ticks.prices.iter() {
+        let y = frame.y_of(price).round() as i32;
+
+        // 価格ラベル
+        let price_text = Text::new()
+            .set("x", PRICE_AXIS_X + 20)  // DOMラダーの右側に配置
+            .set("y", y + 5)
+            .set("text-anchor", "start")
+            .add(TextNode::new(ticks.label(price)));
+
+        // 目盛り線
+        let tick_line = Line::new()
+            .set("x1", PRICE_AXIS_X)  // DOMラダーの終端から開始
+            .set("x2", PRICE_AXIS_X + 10)  // 少し右に伸ばす
+            .set("y1", y)
+            .set("y2", y)
+            .set("stroke", "white")
+            .set("stroke-width", 1);
+
+        price_axis_group = price_axis_group.add(price_text).add(tick_line);
+    }
+
+    // 価格軸グループを追加
+    group.add(price_axis_group)
+}
+
+fn document() -> Document {
+    Document::new()
+        .set("width", "100%")
+        .set("height", "100%")
+        .set("viewBox", format!("0 0 {} {}", HEATMAP_WIDTH, HEATMAP_HEIGHT))
+        .set("preserveAspectRatio", "xMidYMid meet")
+}
+
+/// 配信用の描画結果
+pub struct RasterHeatmap {
+    // HEATMAP_WIDTH×HEATMAP_HEIGHT のヒートマップ本体
+    pub canvas: Canvas,
+    // 同じviewBoxで画像の上に重ねる補助表示のSVG
+    pub overlay: String,
+}
+
+/// 描き直しが必要になる表示条件
+#[derive(Debug, Clone, PartialEq)]
+struct View {
+    aggregation: Aggregation,
+    metric: HeatmapMetric,
+    scale: IntensityScale,
+    colors: ColorMap,
+    window: i64,
+    // 右端の時刻（None は最新に追従）
+    until: Option<i64>,
+    min_price: f64,
+    max_price: f64,
+}
+
+/// 板の更新ごとに最新の1列だけを描き足し、古い列を左へ流すヒートマップ本体
+/// 表示条件が変わったとき、濃淡の基準値が REDRAW_TOLERANCE を超えて動いたとき、履歴が組み直されたときだけ表示範囲の履歴から描き直す
+/// 基準値は毎回ではなく、描き直すときと REFERENCE_INTERVAL_MS ごとにだけ求め直す
+/// 右端を過去に固定しているときは、描き直すまで同じ画像を使う
+pub struct ScrollingHeatmap {
+    body: Canvas,
+    view: Option<View>,
+    // 描画に使っている濃淡の基準値と、それを求めたときの最新のスナップショットの時刻
+    reference: f64,
+    referenced_at: i64,
+    // 右端の列番号
+    head: i64,
+    // 最後に描いたスナップショットの時刻
+    last: Option<i64>,
+    // 描いたときの履歴の組み直し回数
+    revision: u64,
+}
+
+impl ScrollingHeatmap {
+    pub fn new() -> Self {
+        Self {
+            body: Canvas::new(ACTUAL_HEATMAP_WIDTH as u32, HEATMAP_HEIGHT as u32),
+            view: None,
+            reference: 0.0,
+            referenced_at: 0,
+            head: 0,
+            last: None,
+            revision: 0,
+        }
+    }
+
+    /// 列番号をキャンバス上のx座標に変換する（右端が head）
+    fn x_of_column(&self, column: i64) -> i32 {
+        (ACTUAL_HEATMAP_WIDTH as i64 - 1 - (self.head - column)) as i32
+    }
+
+    /// 列を背景色で塗りつぶしてからスナップショットを描く
+    fn paint_columns(&mut self, (buy, sell): (&CompactSide, &CompactSide), metric: HeatmapMetric, frame: &Frame, (x, width): (i32, i32)) {
+        self.body.fill_rect(x, 0, width, HEATMAP_HEIGHT, BACKGROUND_COLOR);
+        paint_snapshot(&mut self.body, buy, sell, metric, frame, (x, width));
+    }
+
+    /// 表示範囲の履歴から描き直す
+    fn redraw(&mut self, state: &OrderBookState, metric: HeatmapMetric, frame: &Frame) {
+        let history = state.history();
+        // since の時点の板を表す1つ前のスナップショットから描く
+        let first = history.partition_point(|(ts, _, _)| *ts <= frame.since).saturating_sub(1);
+        info!("Redrawing heatmap ({} snapshots)", history.len() - first);
+        self.body.fill_rect(0, 0, ACTUAL_HEATMAP_WIDTH, HEATMAP_HEIGHT, BACKGROUND_COLOR);
+        self.head = column_of(frame.until, frame.window);
+
+        // 各スナップショットは次のスナップショットの列の手前まで描く（同じ列に複数あれば最後のもの）
+        for (i, (ts, buy, sell)) in history.iter().enumerate().skip(first) {
+            let column = column_of(*ts, frame.window);
+            if column > self.head {
+                break;
+            }
+            let width = history.get(i + 1).map_or(1, |(next, _, _)| column_of(*next, frame.window) - column);
+            if width <= 0 {
+                continue;
+            }
+            let x = self.x_of_column(column);
+            paint_snapshot(&mut self.body, buy, sell, metric, frame, (x, width as i32));
+        }
+    }
+
+    /// 最新のスナップショットを右端に描き足す
+    fn append(&mut self, previous: (&CompactSide, &CompactSide), latest: (i64, &CompactSide, &CompactSide), metric: HeatmapMetric, frame: &Frame) {
+        let (ts, buy, sell) = latest;
+        let column = column_of(ts, frame.window);
+        let shift = (column - self.head) as i32;
+        if shift > 0 {
+            self.body.scroll_left(shift as u32);
+            self.head = column;
+            // 前回のスナップショットを新しい列の手前まで伸ばす
+            let width = ACTUAL_HEATMAP_WIDTH;
+            self.paint_columns(previous, metric, frame, (width - 1 - shift, shift));
+        }
+        self.paint_columns((buy, sell), metric, frame, (ACTUAL_HEATMAP_WIDTH - 1, 1));
+    }
+
+    /// 前回以降のスナップショットを反映して描画する（履歴がなければ None）
+    pub fn render(
+        &mut self,
+        aggregation: Aggregation,
+        state: &OrderBookState,
+        trades: &TradeWindow,
+        style: &RenderStyle,
+        range: ViewRange,
+    ) -> Option<RasterHeatmap> {
+        let (latest, _, _) = state.history().last()?;
+        let ViewRange { since, until, min_price, max_price } = range;
+        let (metric, scale) = (style.metric, style.scale);
+        let window = until - since;
+        let fixed = (until < *latest).then_some(until);
+        let view = View { aggregation, metric, scale, colors: style.colors.clone(), window, until: fixed, min_price, max_price };
+        // 前回描いたスナップショットの位置（描画の間に複数のスナップショットが増えていることがある）
+        // 間引きで最新のスナップショットが置き換えられていれば、その1つ前から描き直す
+        let drawn = self.last.and_then(|last| state.history().iter().rposition(|(ts, _, _)| *ts <= last));
+
+        // 表示条件・描いた履歴が変わらず、時間幅を超えて進んでもいなければ描き足せる
+        let unchanged = drawn.is_some()
+            && self.view.as_ref() == Some(&view)
+            && state.history_revision() == self.revision
+            && (fixed.is_some() || column_of(*latest, window) - self.head < ACTUAL_HEATMAP_WIDTH as i64);
+        // 濃淡の基準値は描き直すときと REFERENCE_INTERVAL_MS ごとにだけ表示範囲の履歴から求め直し、
+        // 前回の値から REDRAW_TOLERANCE を超えて動いていれば描き直す
+        let reference = if unchanged && *latest - self.referenced_at < REFERENCE_INTERVAL_MS {
+            self.reference
+        } else {
+            self.referenced_at = *latest;
+            reference_of(state, range.times(), metric, scale)?
+        };
+        let incremental = unchanged && (reference - self.reference).abs() <= self.reference * REDRAW_TOLERANCE;
+
+        if !incremental {
+            self.reference = reference;
+            info!("Generating heatmap with reference: {}", reference);
+            info!("Time range: {} to {}, price range: {} to {} (width: {})", since, until, min_price, max_price, max_price - min_price);
+        }
+        let frame = Frame::new(range, style, self.reference);
+        match drawn {
+            // 右端を固定した範囲は新しいスナップショットが増えても変わらない
+            Some(_) if incremental && fixed.is_some() => {}
+            Some(drawn) if incremental => {
+                for pair in state.history()[drawn..].windows(2) {
+                    let ((_, previous_buy, previous_sell), (ts, buy, sell)) = (&pair[0], &pair[1]);
+                    self.append((previous_buy, previous_sell), (*ts, buy, sell), metric, &frame);
+                }
+            }
+            _ => self.redraw(state, metric, &frame),
+        }
+        self.view = Some(view);
+        self.last = Some(*latest);
+        self.revision = state.history_revision();
+
+        // 約定は数が少なく複数の列にまたがるため、毎回まとめて重ねる
+        let mut canvas = Canvas::new(HEATMAP_WIDTH as u32, HEATMAP_HEIGHT as u32);
+        canvas.copy_from(&self.body, 0, 0);
+        paint_trades(&mut canvas, trades, &frame);
+        Some(RasterHeatmap {
+            canvas,
+            overlay: document().add(overlay(state, trades, &frame, style)).to_string(),
+        })
+    }
+}
+
+/// 全体を1つのSVGとして描画する（エクスポート用）
+pub fn render_svg(
+    state: &OrderBookState,
+    trades: &TradeWindow,
+    style: &RenderStyle,
+    range: ViewRange,
+) -> String {
+    let document = document();
+    let Some(reference) = reference_of(state, range.times(), style.metric, style.scale) else {
+        return document.to_string();
+    };
+    let frame = Frame::new(range, style, reference);
+    let mut painter = SvgPainter(Group::new());
+    let clip = ClipPath::new()
+        .set("id", BODY_CLIP_ID)
+        .add(Rectangle::new()
+            .set("x", 0)
+            .set("y", 0)
+            .set("width", ACTUAL_HEATMAP_WIDTH)
+            .set("height", HEATMAP_HEIGHT));
+    // 背景（ヒートマップ部分のみ）
+    painter.fill_rect(0, 0, ACTUAL_HEATMAP_WIDTH, HEATMAP_HEIGHT, BACKGROUND_COLOR);
+    let history = state.history_between((frame.since, frame.until));
+    // 表示範囲の手前から続くスナップショットは左端から描く
+    let x_of = |time: i64| (frame.x_of(time).floor() as i32).clamp(0, ACTUAL_HEATMAP_WIDTH - 1);
+    for (i, (ts, buy, sell)) in history.iter().enumerate() {
+        // 各スナップショットは次のスナップショットの手前まで描く（最新のものは1列）
+        let x = x_of(*ts);
+        let end = history.get(i + 1).map_or(x + 1, |(next, _, _)| x_of(*next));
+        if end <= x {
+            continue;
+        }
+        paint_snapshot(&mut painter, buy, sell, style.metric, &frame, (x, end - x));
+    }
+    paint_trades(&mut painter, trades, &frame);
+    document
+        .add(Definitions::new().add(clip))
+        .add(painter.0)
+        .add(overlay(state, trades, &frame, style))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{side, trade};
+    use crate::history::BucketStat;
+
+    fn style(colors: &ColorMap) -> RenderStyle<'_> {
+        RenderStyle {
+            metric: HeatmapMetric::Size,
+            scale: IntensityScale::default(),
+            colors,
+            time_axis: TimeAxis::default(),
+            tick_size: None,
+        }
+    }
+
+    // 価格範囲は 90〜110
+    fn view((since, until): (i64, i64)) -> ViewRange {
+        ViewRange { since, until, min_price: 90.0, max_price: 110.0 }
+    }
+
+    #[test]
+    fn trade_bubbles_stay_inside_body() {
+        let mut trades = TradeWindow::new(60_000);
+        trades.push(&[trade(1, 60_000, Side::Buy, 100.0, 1.0)]);
+        let frame = Frame::new(view((0, 60_000)), &style(&ColorMap::default()), 1.0);
+        let mut canvas = Canvas::new(HEATMAP_WIDTH as u32, HEATMAP_HEIGHT as u32);
+        paint_trades(&mut canvas, &trades, &frame);
+        // 右端の約定の円は本体の内側だけに描き、DOMラダーの列にはみ出さない
+        let y = frame.y_of(100.0) as u32;
+        assert_ne!(canvas.pixel(ACTUAL_HEATMAP_WIDTH as u32 - 1, y)[3], 0);
+        assert_eq!(canvas.pixel(ACTUAL_HEATMAP_WIDTH as u32, y)[3], 0);
+        assert_eq!(canvas.pixel(ACTUAL_HEATMAP_WIDTH as u32 + 10, y)[3], 0);
+    }
+
+    #[test]
+    fn incremental_columns_match_full_redraw() {
+        const WINDOW: i64 = 30_000;
+        let mut state = OrderBookState::with_history(WINDOW, BucketStat::default(), usize::MAX);
+        let trades = TradeWindow::new(WINDOW);
+        let colors = ColorMap::default();
+        let render = |heatmap: &mut ScrollingHeatmap, state: &OrderBookState| {
+            let latest = state.history().last().unwrap().0;
+            heatmap.render(
+                Aggregation::default(),
+                state,
+                &trades,
+                &style(&colors),
+                view((latest - WINDOW, latest)),
+            );
+        };
+
+        // 30秒の時間幅では1列が約22ミリ秒、履歴は25ミリ秒ごとに1スナップショット
+        // 1_022・1_060・1_310 は直前のスナップショットを間引きで置き換え、1_300 は複数列をまたぐ
+        // 5_000 は閉じた1秒の区間が先頭に入って履歴が組み直され、40_000 は時間幅を超えて進むので全体を描き直す
+        let mut incremental = ScrollingHeatmap::new();
+        for (i, ts) in [1_000, 1_022, 1_050, 1_060, 1_300, 1_310, 5_000, 5_023, 40_000].into_iter().enumerate() {
+            let bid = 95.0 + i as f64;
+            state.buy = side(&[(bid, 1.0, 1)]);
+            state.sell = side(&[(bid + 2.0, 1.0, 1)]);
+            state.update_history(ts);
+
+            render(&mut incremental, &state);
+            let mut full = ScrollingHeatmap::new();
+            render(&mut full, &state);
+            assert_eq!(incremental.head, full.head);
+            assert!(incremental.body == full.body, "incremental drawing differs after the snapshot at {}", ts);
+        }
+    }
+
+    #[test]
+    fn reference_covers_only_visible_range() {
+        let mut state = OrderBookState::with_history(30_000, BucketStat::default(), usize::MAX);
+        for (ts, size) in [(1_000, 1.0), (5_000, 2.0), (20_000, 100.0)] {
+            state.buy = side(&[(100.0, size, 1)]);
+            state.update_history(ts);
+        }
+        let scale = IntensityScale::default();
+        assert_eq!(reference_of(&state, (0, 10_000), HeatmapMetric::Size, scale), Some(2.0));
+        assert_eq!(reference_of(&state, (10_000, 40_000), HeatmapMetric::Size, scale), Some(100.0));
+    }
+
+    #[test]
+    fn svg_bars_follow_snapshot_times() {
+        let mut state = OrderBookState::with_history(60_000, BucketStat::default(), usize::MAX);
+        for (ts, bid) in [(0, 95.0), (45_000, 96.0), (60_000, 97.0)] {
+            state.buy = side(&[(bid, 1.0, 1)]);
+            state.sell = side(&[(bid + 2.0, 1.0, 1)]);
+            state.update_history(ts);
+        }
+        let svg = render_svg(
+            &state,
+            &TradeWindow::new(60_000),
+            &style(&ColorMap::default()),
+            view((0, 60_000)),
+        );
+        // 45秒続いた板は本体の3/4、15秒続いた板は残り（最新の1列を除く）の幅になる
+        let widths = |width: i32| svg.matches(&format!(" width=\"{}\"", width)).count();
+        assert_eq!(widths(1_020), 3);
+        assert_eq!(widths(ACTUAL_HEATMAP_WIDTH - 1 - 1_020), 3);
+        assert_eq!(widths(1), 3);
+    }
+}
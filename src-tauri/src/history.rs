@@ -0,0 +1,612 @@
+// 板の履歴
+// 直近は受信したスナップショットをそのまま持ち、それより古い分は 1秒・10秒・1分の区間ごとに集計して持つ
+// 区間ごとに価格レベルの最大・平均・最後の数量を保持するので、数時間分でもメモリは一定に収まる
+// スナップショットは価格順の配列（1側1回の確保）で持ち、全体の使用量が上限を超えたら古い区間から捨てる
+use crate::book::{BookSide, Level, MAX_WINDOW_MS};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::Arc;
+
+// 受信したままのスナップショットを保持する期間
+const RAW_MS: i64 = 60_000;
+// 受信したままのスナップショットの上限（リングバッファの容量）
+// 保持期間をこの数の区間に分け、同じ区間のスナップショットは最新のものだけを残す
+const MAX_SNAPSHOTS: usize = 1200;
+// 集計の段階（区間の長さ, 保持期間）。細かい順
+const TIERS: [(i64, i64); 3] = [
+    (1_000, 10 * 60_000),
+    (10_000, 60 * 60_000),
+    (60_000, MAX_WINDOW_MS),
+];
+// 区間が閉じても並びはこの間隔でしか組み直さない（それまでは受信したままのものが残るだけで、並びは正しい）
+const COMPOSE_MS: i64 = 5_000;
+// 上限を超えたときは、この割合まで減らす（上限付近で毎回捨て直さないようにする）
+const EVICT_TARGET: f64 = 0.9;
+
+/// 片側の板を価格順に並べた配列（複製しても中身は共有する）
+#[derive(Debug, Clone, Default)]
+pub struct CompactSide(Arc<[(OrderedFloat<f64>, Level)]>);
+
+impl CompactSide {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&OrderedFloat<f64>, &Level)> + ExactSizeIterator {
+        self.0.iter().map(|(price, level)| (price, level))
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &OrderedFloat<f64>> + ExactSizeIterator {
+        self.0.iter().map(|(price, _)| price)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &Level> + ExactSizeIterator {
+        self.0.iter().map(|(_, level)| level)
+    }
+
+    /// 価格ちょうどのレベル
+    pub fn get(&self, price: f64) -> Option<&Level> {
+        let index = self.0.binary_search_by(|(p, _)| p.0.total_cmp(&price)).ok()?;
+        Some(&self.0[index].1)
+    }
+
+    /// 同じ配列を共有しているか
+    fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// 使用しているメモリの概算（バイト）
+    fn bytes(&self) -> usize {
+        2 * size_of::<usize>() + self.0.len() * size_of::<(OrderedFloat<f64>, Level)>()
+    }
+}
+
+impl From<&BookSide> for CompactSide {
+    fn from(side: &BookSide) -> Self {
+        Self(side.iter().map(|(price, level)| (*price, *level)).collect())
+    }
+}
+
+impl FromIterator<(OrderedFloat<f64>, Level)> for CompactSide {
+    fn from_iter<I: IntoIterator<Item = (OrderedFloat<f64>, Level)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// 時刻と、その時点の両側の板（集計した区間では区間の開始時刻と、区間を代表する板）
+pub type Snapshot = (i64, CompactSide, CompactSide);
+
+fn snapshot_bytes((_, buy, sell): &Snapshot) -> usize {
+    size_of::<Snapshot>() + buy.bytes() + sell.bytes()
+}
+
+/// 集計した区間を描画するときに使う値
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BucketStat {
+    // 区間内で最も数量が大きかったときの価格レベル（一瞬だけ出た壁も残る）
+    #[default]
+    Max,
+    // 区間内の平均（板がなかった時間は 0 として平均する）
+    Mean,
+    // 区間の最後の板
+    Last,
+}
+
+/// 区間内の1つの価格レベルの集計
+#[derive(Debug, Clone, Copy)]
+struct LevelStats {
+    max: Level,
+    size_sum: f64,
+    orders_sum: f64,
+}
+
+impl LevelStats {
+    fn merge(&mut self, other: &LevelStats) {
+        if other.max.size > self.max.size {
+            self.max = other.max;
+        }
+        self.size_sum += other.size_sum;
+        self.orders_sum += other.orders_sum;
+    }
+}
+
+impl From<&Level> for LevelStats {
+    fn from(level: &Level) -> Self {
+        Self { max: *level, size_sum: level.size, orders_sum: level.orders as f64 }
+    }
+}
+
+// 価格順に並べた集計
+type StatsSide = Vec<(OrderedFloat<f64>, LevelStats)>;
+
+const STATS_ENTRY_BYTES: usize = size_of::<(OrderedFloat<f64>, LevelStats)>();
+
+/// 価格順の集計に、価格順に並んだ別の集計を加える
+fn merge_stats<I>(stats: &mut StatsSide, other: I)
+where
+    I: ExactSizeIterator<Item = (OrderedFloat<f64>, LevelStats)> + Clone,
+{
+    // 板の価格が変わっていなければ、その場で足すだけで済む
+    if stats.len() == other.len() && stats.iter().zip(other.clone()).all(|((a, _), (b, _))| *a == b) {
+        for ((_, s), (_, level)) in stats.iter_mut().zip(other) {
+            s.merge(&level);
+        }
+        return;
+    }
+    let mut merged = Vec::with_capacity(stats.len().max(other.len()));
+    let mut mine = std::mem::take(stats).into_iter().peekable();
+    for (price, level) in other {
+        while let Some(entry) = mine.next_if(|(p, _)| *p < price) {
+            merged.push(entry);
+        }
+        match mine.next_if(|(p, _)| *p == price) {
+            Some((_, mut s)) => {
+                s.merge(&level);
+                merged.push((price, s));
+            }
+            None => merged.push((price, level)),
+        }
+    }
+    merged.extend(mine);
+    *stats = merged;
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    // 区間の開始時刻
+    time: i64,
+    // 集計したスナップショットの数
+    samples: u32,
+    buy: StatsSide,
+    sell: StatsSide,
+    last: (CompactSide, CompactSide),
+}
+
+impl Bucket {
+    fn new(time: i64) -> Self {
+        Self {
+            time,
+            samples: 0,
+            buy: StatsSide::new(),
+            sell: StatsSide::new(),
+            last: Default::default(),
+        }
+    }
+
+    /// スナップショット1つを集計に加える
+    fn add(&mut self, (_, buy, sell): &Snapshot) {
+        self.samples += 1;
+        for (stats, side) in [(&mut self.buy, buy), (&mut self.sell, sell)] {
+            merge_stats(stats, side.0.iter().map(|(price, level)| (*price, LevelStats::from(level))));
+        }
+        self.last = (buy.clone(), sell.clone());
+    }
+
+    /// より新しい区間の集計を加える
+    fn merge(&mut self, other: &Bucket) {
+        self.samples += other.samples;
+        for (stats, other) in [(&mut self.buy, &other.buy), (&mut self.sell, &other.sell)] {
+            merge_stats(stats, other.iter().copied());
+        }
+        self.last = other.last.clone();
+    }
+
+    fn snapshot(&self, stat: BucketStat) -> Snapshot {
+        let samples = self.samples.max(1) as f64;
+        let side = |stats: &StatsSide| -> CompactSide {
+            stats.iter().map(|(price, s)| {
+                let level = match stat {
+                    BucketStat::Mean => Level {
+                        size: s.size_sum / samples,
+                        orders: (s.orders_sum / samples).round().max(1.0) as i32,
+                    },
+                    _ => s.max,
+                };
+                (*price, level)
+            }).collect()
+        };
+        match stat {
+            BucketStat::Last => (self.time, self.last.0.clone(), self.last.1.clone()),
+            _ => (self.time, side(&self.buy), side(&self.sell)),
+        }
+    }
+
+    /// 区間と、それを代表するスナップショットの使用量の概算
+    fn bytes(&self, snapshot: &Snapshot) -> usize {
+        size_of::<Bucket>() + (self.buy.len() + self.sell.len()) * STATS_ENTRY_BYTES + snapshot_bytes(snapshot)
+    }
+}
+
+/// 1つの集計の段階
+struct Tier {
+    length: i64,
+    retention: i64,
+    // 集計中の区間
+    open: Option<Bucket>,
+    // 閉じた区間と、それを代表するスナップショット（古い順）
+    closed: VecDeque<(Bucket, Snapshot)>,
+}
+
+impl Tier {
+    fn start_of(&self, time: i64) -> i64 {
+        time.div_euclid(self.length) * self.length
+    }
+
+    /// time を含む区間を集計中にする。それまで集計していた区間が閉じたら true を返す
+    fn open_at(&mut self, time: i64, stat: BucketStat) -> bool {
+        let start = self.start_of(time);
+        if self.open.as_ref().is_some_and(|open| start <= open.time) {
+            return false;
+        }
+        let Some(closed) = self.open.replace(Bucket::new(start)) else {
+            return false;
+        };
+        let snapshot = closed.snapshot(stat);
+        self.closed.push_back((closed, snapshot));
+        true
+    }
+}
+
+pub struct History {
+    // 受信したままのスナップショット（容量 MAX_SNAPSHOTS のリングバッファ）
+    raw: VecDeque<Snapshot>,
+    tiers: Vec<Tier>,
+    stat: BucketStat,
+    // 保持する期間（表示する時間幅）
+    window: i64,
+    // 使用量の上限と現在の使用量の概算（バイト）
+    max_bytes: usize,
+    bytes: usize,
+    // 時間幅内のスナップショットを古い順に並べたもの（先頭の start 個は捨てたもの）
+    // 粗い段階から順に、より細かい段階が持っていない時刻のものだけを使う
+    composed: Vec<Snapshot>,
+    start: usize,
+    // 最後に組み直した時刻と、それ以降に区間が閉じたかどうか
+    composed_at: i64,
+    stale: bool,
+    // 組み直しで末尾以外のスナップショットが入れ替わった回数
+    revision: u64,
+}
+
+impl History {
+    pub fn new(window: i64, stat: BucketStat, max_bytes: usize) -> Self {
+        Self {
+            raw: VecDeque::with_capacity(MAX_SNAPSHOTS),
+            tiers: TIERS.iter().map(|&(length, retention)| Tier {
+                length,
+                retention,
+                open: None,
+                closed: VecDeque::new(),
+            }).collect(),
+            stat,
+            window,
+            max_bytes,
+            bytes: 0,
+            composed: Vec::new(),
+            start: 0,
+            composed_at: 0,
+            stale: false,
+            revision: 0,
+        }
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.composed[self.start..]
+    }
+
+    pub fn window(&self) -> i64 {
+        self.window
+    }
+
+    /// 末尾への追加・置き換え以外で並びが変わるたびに増える
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// 受信したままのスナップショット1つあたりの時間（ミリ秒）
+    fn resolution(&self) -> i64 {
+        (self.window.min(RAW_MS) / MAX_SNAPSHOTS as i64).max(1)
+    }
+
+    pub fn push(&mut self, time: i64, buy: &BookSide, sell: &BookSide) {
+        let snapshot: Snapshot = (time, buy.into(), sell.into());
+
+        // 細かい段階から順に集計し、閉じた区間を次の段階へ渡す
+        let mut rolled = false;
+        for i in 0..self.tiers.len() {
+            let (finer, coarser) = self.tiers.split_at_mut(i + 1);
+            let tier = &mut finer[i];
+            if !tier.open_at(time, self.stat) {
+                break;
+            }
+            rolled = true;
+            let Some((closed, snapshot)) = tier.closed.back() else {
+                break;
+            };
+            self.bytes += closed.bytes(snapshot);
+            if let Some(next) = coarser.first_mut() {
+                let start = next.start_of(closed.time);
+                next.open.get_or_insert_with(|| Bucket::new(start)).merge(closed);
+            }
+        }
+        if let Some(open) = self.tiers[0].open.as_mut() {
+            open.add(&snapshot);
+        }
+
+        let replaced = self.push_raw(snapshot.clone());
+        // 並びの末尾を更新する
+        let live = self.start < self.composed.len();
+        match self.composed.last_mut() {
+            Some(last) if replaced && live => *last = snapshot,
+            _ => self.composed.push(snapshot),
+        }
+        // 閉じた区間は間隔を空けてまとめて反映する。上限を超えて捨てたときは、捨てた分を手放すためにすぐ組み直す
+        self.stale |= rolled;
+        let due = self.stale && time.div_euclid(COMPOSE_MS) != self.composed_at.div_euclid(COMPOSE_MS);
+        if self.evict(time) || due {
+            self.compose();
+        } else {
+            self.trim(time);
+        }
+    }
+
+    /// 受信したままのスナップショットを加える（直前のものと同じ区間なら置き換えて true を返す）
+    fn push_raw(&mut self, snapshot: Snapshot) -> bool {
+        let resolution = self.resolution();
+        self.bytes += snapshot_bytes(&snapshot);
+        if let Some(last) = self.raw.back_mut().filter(|last| last.0.div_euclid(resolution) == snapshot.0.div_euclid(resolution)) {
+            self.bytes -= snapshot_bytes(last);
+            *last = snapshot;
+            return true;
+        }
+        if self.raw.len() == MAX_SNAPSHOTS {
+            if let Some(oldest) = self.raw.pop_front() {
+                self.bytes -= snapshot_bytes(&oldest);
+            }
+        }
+        self.raw.push_back(snapshot);
+        false
+    }
+
+    /// 時間幅より前のスナップショットを捨てる（左端まで描けるよう、時間幅の直前の1つは残す）
+    fn trim(&mut self, latest: i64) {
+        let stale = self.snapshots().partition_point(|(ts, _, _)| *ts <= latest - self.window);
+        self.start += stale.saturating_sub(1);
+        // 捨てた分が半分を超えたら詰める
+        if self.start * 2 > self.composed.len() {
+            self.composed.drain(..self.start);
+            self.start = 0;
+        }
+    }
+
+    /// 保持期間を変更し、今ある履歴をそれに合わせて間引く
+    pub fn set_window(&mut self, window: i64) {
+        self.window = window;
+        for snapshot in std::mem::take(&mut self.raw) {
+            self.bytes -= snapshot_bytes(&snapshot);
+            self.push_raw(snapshot);
+        }
+        if let Some(&(latest, _, _)) = self.raw.back() {
+            self.evict(latest);
+        }
+        self.compose();
+    }
+
+    /// 使用量の上限を変更する
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        if let Some(&(latest, _, _)) = self.raw.back() {
+            self.evict(latest);
+        }
+        self.compose();
+    }
+
+    /// 集計した区間の代表値を切り替える
+    pub fn set_stat(&mut self, stat: BucketStat) {
+        self.stat = stat;
+        for tier in self.tiers.iter_mut() {
+            for (bucket, snapshot) in tier.closed.iter_mut() {
+                self.bytes -= bucket.bytes(snapshot);
+                *snapshot = bucket.snapshot(stat);
+                self.bytes += bucket.bytes(snapshot);
+            }
+        }
+        self.compose();
+    }
+
+    /// 保持期間を過ぎたスナップショットと区間を捨て、使用量が上限を超えていれば古い区間から捨てる
+    /// 上限を超えて捨てたときは true を返す
+    fn evict(&mut self, latest: i64) -> bool {
+        let since = latest - self.window.min(RAW_MS);
+        while let Some(oldest) = self.raw.front().filter(|(ts, _, _)| *ts <= since) {
+            self.bytes -= snapshot_bytes(oldest);
+            self.raw.pop_front();
+        }
+        for tier in self.tiers.iter_mut() {
+            let since = latest - self.window.min(tier.retention);
+            while let Some((bucket, snapshot)) = tier.closed.front().filter(|(bucket, _)| bucket.time + tier.length <= since) {
+                self.bytes -= bucket.bytes(snapshot);
+                tier.closed.pop_front();
+            }
+        }
+
+        if self.bytes <= self.max_bytes {
+            return false;
+        }
+        let target = (self.max_bytes as f64 * EVICT_TARGET) as usize;
+        while self.bytes > target {
+            // 最も古いデータは最も粗い段階にある
+            if let Some(tier) = self.tiers.iter_mut().rev().find(|tier| !tier.closed.is_empty()) {
+                if let Some((bucket, snapshot)) = tier.closed.pop_front() {
+                    self.bytes -= bucket.bytes(&snapshot);
+                }
+            } else if self.raw.len() > 1 {
+                if let Some(oldest) = self.raw.pop_front() {
+                    self.bytes -= snapshot_bytes(&oldest);
+                }
+            } else {
+                break;
+            }
+        }
+        true
+    }
+
+    fn compose(&mut self) {
+        self.stale = false;
+        let (Some(&(oldest, _, _)), Some(&(latest, _, _))) = (self.raw.front(), self.raw.back()) else {
+            self.composed.clear();
+            self.start = 0;
+            return;
+        };
+        self.composed_at = latest;
+
+        // 細かい段階から順に、それより細かい段階の最初の時刻より前の区間を集める
+        let mut boundary = oldest;
+        let mut segments = Vec::with_capacity(self.tiers.len());
+        for tier in self.tiers.iter() {
+            let end = tier.closed.partition_point(|(bucket, _)| bucket.time < boundary);
+            if let Some((first, _)) = tier.closed.front().filter(|_| end > 0) {
+                boundary = first.time;
+            }
+            segments.push(tier.closed.range(..end).map(|(_, snapshot)| snapshot));
+        }
+        let next: Vec<&Snapshot> = segments.into_iter().rev().flatten().chain(self.raw.iter()).collect();
+        let next = &next[next.partition_point(|(ts, _, _)| *ts <= latest - self.window).saturating_sub(1)..];
+
+        // 前回の並びのうち、時間幅から外れた分は先に捨てたものとみなす
+        if let Some(&&(first, _, _)) = next.first() {
+            self.start += self.composed[self.start..].partition_point(|(ts, _, _)| *ts < first);
+        }
+        // 前回と同じ先頭と末尾はそのまま使い、間だけを置き換える
+        let same = |a: &Snapshot, b: &&Snapshot| a.0 == b.0 && a.1.ptr_eq(&b.1) && a.2.ptr_eq(&b.2);
+        let current = &self.composed[self.start..];
+        let prefix = current.iter().zip(next).take_while(|(a, b)| same(a, b)).count();
+        let suffix = current[prefix..].iter().rev()
+            .zip(next[prefix..].iter().rev())
+            .take_while(|(a, b)| same(a, b))
+            .count();
+        let end = self.composed.len() - suffix;
+        if end > self.start + prefix || next.len() - suffix > prefix {
+            self.revision += 1;
+        }
+        self.composed.splice(self.start + prefix..end, next[prefix..next.len() - suffix].iter().map(|s| (*s).clone()));
+        self.trim(latest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::side;
+
+    fn times(history: &History) -> Vec<i64> {
+        history.snapshots().iter().map(|(ts, _, _)| *ts).collect()
+    }
+
+    fn level(snapshot: &Snapshot, price: f64) -> Option<Level> {
+        snapshot.1.iter().find(|(p, _)| **p == OrderedFloat(price)).map(|(_, level)| *level)
+    }
+
+    #[test]
+    fn keeps_latest_raw_snapshot_per_interval() {
+        // 30秒の時間幅では25ミリ秒ごとに1スナップショット
+        let mut history = History::new(30_000, BucketStat::Max, usize::MAX);
+        for ts in [0, 10, 30, 70, 74] {
+            history.push(ts, &BookSide::new(), &BookSide::new());
+        }
+        assert_eq!(times(&history), vec![10, 30, 74]);
+    }
+
+    #[test]
+    fn bucket_holds_max_mean_and_last() {
+        let snapshots = [
+            (0, side(&[(100.0, 1.0, 1)])),
+            (400, side(&[(100.0, 3.0, 2), (101.0, 6.0, 3)])),
+            (800, side(&[(101.0, 3.0, 3)])),
+        ];
+        let mut bucket = Bucket::new(0);
+        bucket.add(&(0, (&snapshots[0].1).into(), CompactSide::default()));
+        // 後半は別の区間で集計してから合わせる
+        let mut later = Bucket::new(400);
+        for (ts, buy) in snapshots[1..].iter() {
+            later.add(&(*ts, buy.into(), CompactSide::default()));
+        }
+        bucket.merge(&later);
+        let level = |stat: BucketStat, price: f64| level(&bucket.snapshot(stat), price);
+
+        assert_eq!(level(BucketStat::Max, 100.0).map(|l| (l.size, l.orders)), Some((3.0, 2)));
+        assert_eq!(level(BucketStat::Mean, 100.0).map(|l| l.size), Some(4.0 / 3.0));
+        assert_eq!(level(BucketStat::Mean, 101.0).map(|l| (l.size, l.orders)), Some((3.0, 2)));
+        assert!(level(BucketStat::Last, 100.0).is_none());
+        assert_eq!(level(BucketStat::Last, 101.0).map(|l| l.size), Some(3.0));
+    }
+
+    #[test]
+    fn older_snapshots_are_replaced_by_buckets() {
+        let mut history = History::new(300_000, BucketStat::Max, usize::MAX);
+        for ts in (0..=300_000).step_by(500) {
+            history.push(ts, &side(&[(100.0, ts as f64, 1)]), &BookSide::new());
+        }
+        let times = times(&history);
+        // 直近1分は受信したまま、それより前は1秒ごと（300秒はちょうど組み直す時刻に当たる）
+        assert_eq!(times.len(), 241 + 120);
+        assert_eq!(&times[..3], &[0, 1_000, 2_000]);
+        assert_eq!(&times[240..243], &[240_000, 240_500, 241_000]);
+        // 区間の代表値は区間内の最大
+        assert_eq!(level(&history.snapshots()[1], 100.0).map(|l| l.size), Some(1_500.0));
+
+        history.set_stat(BucketStat::Last);
+        assert_eq!(level(&history.snapshots()[1], 100.0).map(|l| l.size), Some(1_500.0));
+        history.set_stat(BucketStat::Mean);
+        assert_eq!(level(&history.snapshots()[1], 100.0).map(|l| l.size), Some(1_250.0));
+    }
+
+    #[test]
+    fn long_windows_stay_bounded() {
+        let mut history = History::new(MAX_WINDOW_MS, BucketStat::Max, usize::MAX);
+        let end = 2 * 60 * 60 * 1000;
+        let book = side(&[(100.0, 1.0, 1)]);
+        for ts in (0..=end).step_by(100) {
+            history.push(ts, &book, &BookSide::new());
+        }
+        let times = times(&history);
+        assert_eq!(times.first(), Some(&0));
+        assert_eq!(times.last(), Some(&end));
+        assert!(times.windows(2).all(|w| w[0] < w[1]));
+        assert!(times.len() < 2000, "{} snapshots", times.len());
+        assert!(history.raw.len() <= MAX_SNAPSHOTS);
+        assert!(history.tiers.iter().all(|tier| tier.closed.len() as i64 <= tier.retention / tier.length));
+
+        // 時間幅を縮めると古い分は捨てる
+        history.set_window(60_000);
+        assert!(history.snapshots().iter().all(|(ts, _, _)| *ts >= end - 60_000));
+    }
+
+    #[test]
+    fn memory_cap_drops_oldest_buckets_first() {
+        let book = side(&(0..20).map(|i| (100.0 + i as f64, 1.0, 1)).collect::<Vec<_>>());
+        let push_hour = |history: &mut History| {
+            for ts in (0..=60 * 60 * 1000).step_by(250) {
+                history.push(ts, &book, &book);
+            }
+        };
+        let mut unbounded = History::new(MAX_WINDOW_MS, BucketStat::Max, usize::MAX);
+        push_hour(&mut unbounded);
+
+        let cap = unbounded.bytes() / 2;
+        let mut capped = History::new(MAX_WINDOW_MS, BucketStat::Max, cap);
+        push_hour(&mut capped);
+        assert!(capped.bytes() <= cap);
+        // 直近は残り、古い側が削られる
+        assert_eq!(capped.snapshots().last().map(|s| s.0), unbounded.snapshots().last().map(|s| s.0));
+        assert!(capped.snapshots()[0].0 > unbounded.snapshots()[0].0);
+
+        // 上限を下げるとその場で減らす
+        capped.set_max_bytes(cap / 4);
+        assert!(capped.bytes() <= cap / 4);
+        assert!(!capped.snapshots().is_empty());
+    }
+}
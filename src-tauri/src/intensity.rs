@@ -0,0 +1,113 @@
+// ヒートマップの濃淡（強度）の付け方
+// 1つの大きな壁に引きずられて他が見えなくならないよう、基準値と曲線を選べるようにする
+use serde::{Deserialize, Serialize};
+
+// 対数スケールで、基準値の約1/1000までの値を見分けられるようにする
+const LOG_GAIN: f64 = 1000.0;
+
+/// 基準値に対する比率を強度に変換する曲線
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ScaleCurve {
+    #[default]
+    Linear,
+    Sqrt,
+    Log,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IntensityScale {
+    pub curve: ScaleCurve,
+    // 基準値にするパーセンタイル（例: 99.0）。これを超える値は最大の強度に飽和させる
+    // None は最大値を基準にする
+    pub clip_percentile: Option<f64>,
+}
+
+impl IntensityScale {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(p) = self.clip_percentile {
+            if !(p > 0.0 && p <= 100.0) {
+                return Err(format!("clip percentile must be in (0, 100]: {}", p));
+            }
+        }
+        Ok(())
+    }
+
+    /// 強度 1.0 に対応する値（正の値がなければ None）
+    pub fn reference(&self, values: impl Iterator<Item = f64>) -> Option<f64> {
+        let reference = match self.clip_percentile {
+            None => values.fold(0.0f64, f64::max),
+            Some(p) => {
+                let mut values: Vec<f64> = values.filter(|v| *v > 0.0).collect();
+                if values.is_empty() {
+                    return None;
+                }
+                let index = ((p / 100.0 * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1;
+                *values.select_nth_unstable_by(index, f64::total_cmp).1
+            }
+        };
+        (reference > 0.0).then_some(reference)
+    }
+
+    /// 基準値に対する強度（0.0〜1.0）
+    pub fn intensity(&self, value: f64, reference: f64) -> f64 {
+        let ratio = (value / reference).clamp(0.0, 1.0);
+        match self.curve {
+            ScaleCurve::Linear => ratio,
+            ScaleCurve::Sqrt => ratio.sqrt(),
+            ScaleCurve::Log => (1.0 + LOG_GAIN * ratio).ln() / (1.0 + LOG_GAIN).ln(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(curve: ScaleCurve, clip_percentile: Option<f64>) -> IntensityScale {
+        IntensityScale { curve, clip_percentile }
+    }
+
+    #[test]
+    fn reference_is_max_without_clipping() {
+        let values = [1.0, 5.0, 3.0, 0.0];
+        assert_eq!(scale(ScaleCurve::Linear, None).reference(values.into_iter()), Some(5.0));
+        assert_eq!(scale(ScaleCurve::Linear, None).reference([0.0, 0.0].into_iter()), None);
+    }
+
+    #[test]
+    fn percentile_clips_outliers() {
+        // 1〜99 と、桁違いの壁が1つ
+        let values = (1..100).map(f64::from).chain([1_000_000.0]);
+        let reference = scale(ScaleCurve::Linear, Some(99.0)).reference(values).unwrap();
+        assert_eq!(reference, 99.0);
+        assert_eq!(scale(ScaleCurve::Linear, Some(100.0)).reference((1..=10).map(f64::from)), Some(10.0));
+        // 板のない価格（0）は分布に含めない
+        assert_eq!(scale(ScaleCurve::Linear, Some(50.0)).reference([0.0, 0.0, 0.0, 2.0, 4.0].into_iter()), Some(2.0));
+    }
+
+    #[test]
+    fn curves_map_reference_to_full_intensity() {
+        for curve in [ScaleCurve::Linear, ScaleCurve::Sqrt, ScaleCurve::Log] {
+            let scale = scale(curve, None);
+            assert_eq!(scale.intensity(0.0, 10.0), 0.0);
+            assert!((scale.intensity(10.0, 10.0) - 1.0).abs() < 1e-12);
+            assert!((scale.intensity(50.0, 10.0) - 1.0).abs() < 1e-12);
+        }
+        // 小さな値ほど線形より持ち上がる
+        let (linear, sqrt, log) = (
+            scale(ScaleCurve::Linear, None).intensity(0.01, 1.0),
+            scale(ScaleCurve::Sqrt, None).intensity(0.01, 1.0),
+            scale(ScaleCurve::Log, None).intensity(0.01, 1.0),
+        );
+        assert!(linear < sqrt && sqrt < log);
+    }
+
+    #[test]
+    fn rejects_invalid_percentile() {
+        assert!(scale(ScaleCurve::Log, Some(0.0)).validate().is_err());
+        assert!(scale(ScaleCurve::Log, Some(100.5)).validate().is_err());
+        assert!(scale(ScaleCurve::Log, Some(99.5)).validate().is_ok());
+    }
+}
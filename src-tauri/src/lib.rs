@@ -1,179 +1,192 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use futures_util::{SinkExt, StreamExt};
-use parking_lot::RwLock;
-use serde::{Deserialize, Serialize};
+mod axis;
+// book と history は履歴のベンチマーク（benches/）から使うためだけに公開する
+#[doc(hidden)]
+pub mod book;
+mod colormap;
+mod cvd;
+mod dom;
+mod feed;
+mod frame;
+mod grid;
+mod heatmap;
+#[doc(hidden)]
+pub mod history;
+mod intensity;
+mod market;
+mod profile;
+mod raster;
+mod readout;
+#[cfg(test)]
+mod testing;
+mod viewport;
+mod wire;
+
+use base64::Engine;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::sync::Arc;
-use svg::node::element::Rectangle;
-use svg::node::element::Text;
-use svg::node::Text as TextNode;
-use svg::Document;
-use tauri::Emitter;
-use tokio::sync::mpsc;
-use tokio_tungstenite::connect_async;
-use url::Url;
+use std::time::Instant;
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{Emitter, Manager};
+use tokio::sync::{mpsc, watch};
+use tokio::time::MissedTickBehavior;
+use axis::TimeAxis;
+use book::{validate_history_limit, validate_window, AggregationMode, BookMap, CoinBook, HeatmapMetric, HeatmapOutput, Level};
+use colormap::ColorMap;
+use feed::{Aggregation, WsMessage};
+use frame::{frame_period, validate_frame_rate, FrameStats, PendingFrames, DEFAULT_FRAME_RATE};
+use grid::HeatmapGrid;
+use heatmap::{RasterHeatmap, RenderStyle, ScrollingHeatmap};
+use history::BucketStat;
+use intensity::IntensityScale;
+use wire::BookEncoder;
+use market::{Market, MarketCatalog};
+use readout::{BookReadout, ReadoutPoint};
+use viewport::{PriceViewport, TimeViewport, ViewAxes, ViewRange};
 use ordered_float::OrderedFloat;
 use log::{info, error, warn, LevelFilter};
-use svg::node::element::Group;
-use svg::node::element::Line;
 
-const HEATMAP_WIDTH: i32 = 1920;
-const HEATMAP_HEIGHT: i32 = 1080;
-const RIGHT_MARGIN: i32 = 300;  // 右側の余白
-const ACTUAL_HEATMAP_WIDTH: i32 = HEATMAP_WIDTH - RIGHT_MARGIN;  // ヒートマップの実際の描画幅
+const DEFAULT_COIN: &str = "@107";  // 起動時に購読する銘柄
+const UPDATE_EVENT: &str = "orderbook-update";  // 描画済みヒートマップのイベント
+const GRID_EVENT: &str = "orderbook-grid";  // 数値グリッドのイベント
 
-#[derive(Debug, Deserialize)]
-struct WsLevel {
-    px: String,
-    sz: String,
-    n: i32,
-}
-
-#[derive(Debug, Deserialize)]
-struct WsBook {
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct HeatmapData {
     coin: String,
-    levels: Vec<Vec<WsLevel>>,
-    time: i64,
+    // 描画に使った集約レベルと時間・価格範囲
+    aggregation: Aggregation,
+    since: i64,
+    until: i64,
+    min_price: f64,
+    max_price: f64,
+    // ヒートマップ本体のPNG（data URL）と、その上に重ねる補助表示のSVG
+    image: String,
+    overlay: String
 }
 
-#[derive(Debug, Deserialize)]
-struct WsMessage {
-    channel: String,
-    data: WsBook,
+/// 描画を終えてPNGへの変換を待つヒートマップ（板のロックは手放してある）
+struct RenderedHeatmap {
+    coin: String,
+    aggregation: Aggregation,
+    range: ViewRange,
+    heatmap: RasterHeatmap,
 }
 
-#[derive(Debug, Serialize, Clone)]
-struct HeatmapData {
-    svg: String
+/// 板の更新をバイナリで受け取る送信先
+struct BookStream {
+    coin: String,
+    channel: Channel,
+    encoder: BookEncoder,
 }
 
-struct OrderBookState {
-    buy: BTreeMap<OrderedFloat<f64>, f64>,
-    sell: BTreeMap<OrderedFloat<f64>, f64>,
-    history: Vec<(i64, BTreeMap<OrderedFloat<f64>, f64>, BTreeMap<OrderedFloat<f64>, f64>)>,
+/// Tauriコマンドとバックグラウンドタスクで共有する状態
+#[derive(Clone)]
+struct AppState {
+    books: Arc<RwLock<BookMap>>,
+    markets: Arc<RwLock<MarketCatalog>>,
+    // 購読内容が変わったことをWebSocketタスクへ通知する
+    resubscribe: Arc<watch::Sender<()>>,
+    streams: Arc<Mutex<Vec<BookStream>>>,
+    // 描画待ちの銘柄とフレームレート・描画の統計
+    pending: Arc<Mutex<PendingFrames>>,
+    frame_rate: Arc<watch::Sender<u32>>,
+    frame_stats: Arc<Mutex<FrameStats>>,
 }
 
-impl OrderBookState {
+impl AppState {
     fn new() -> Self {
+        let mut books = BookMap::new();
+        books.insert(DEFAULT_COIN.to_string(), Arc::new(RwLock::new(CoinBook::new(AggregationMode::default()))));
         Self {
-            buy: BTreeMap::new(),
-            sell: BTreeMap::new(),
-            history: Vec::with_capacity(300), // 5分間のデータ（1秒あたり1フレーム）
+            books: Arc::new(RwLock::new(books)),
+            markets: Arc::new(RwLock::new(MarketCatalog::default())),
+            resubscribe: Arc::new(watch::channel(()).0),
+            streams: Arc::new(Mutex::new(Vec::new())),
+            pending: Arc::new(Mutex::new(PendingFrames::default())),
+            frame_rate: Arc::new(watch::channel(DEFAULT_FRAME_RATE).0),
+            frame_stats: Arc::new(Mutex::new(FrameStats::default())),
         }
     }
 
-    fn update_history(&mut self, timestamp: i64) {
-        // 履歴を更新
-        self.history.push((
-            timestamp,
-            self.buy.clone(),
-            self.sell.clone(),
-        ));
-
-        // 5分（300秒）より古いデータを削除
-        let five_minutes_ago = timestamp - 300_000; // ミリ秒位
-        self.history.retain(|(ts, _, _)| *ts > five_minutes_ago);
+    fn notify_resubscribe(&self) {
+        self.resubscribe.send_replace(());
     }
 }
 
-async fn start_websocket_connection(app_handle: tauri::AppHandle) {
+/// 銘柄ごとのイベント名（Tauriのイベント名に使えない "@" などは "_" に置換）
+fn event_name(prefix: &str, coin: &str) -> String {
+    let coin: String = coin
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '/' | ':' | '_') { c } else { '_' })
+        .collect();
+    format!("{}:{}", prefix, coin)
+}
+
+async fn start_websocket_connection(app_handle: tauri::AppHandle, app_state: AppState) {
     info!("Starting WebSocket connection...");
-    let state = Arc::new(RwLock::new(OrderBookState::new()));
+    let books = app_state.books.clone();
+    let streams = app_state.streams.clone();
     let (tx, mut rx) = mpsc::channel(100);
 
-    // WebSocket接続を開始（sig_figs = 5のみ）
-    let tx = tx.clone();
-    let url = Url::parse("wss://api.hyperliquid.xyz/ws").unwrap();
-    
-    let _handle = app_handle.clone();
-    tokio::spawn(async move {
-        let mut retry_count = 0;
-        loop {
-            info!("Connecting to WebSocket with sig_figs: 5 (attempt: {})", retry_count + 1);
-            
-            // 指数バックオフによる再試行待機
-            if retry_count > 0 {
-                let wait_time = std::cmp::min(1 << retry_count, 30); // 最大30秒
-                tokio::time::sleep(tokio::time::Duration::from_secs(wait_time)).await;
-            }
+    // 集約レベルごとにWebSocket接続を開始
+    tokio::spawn(feed::run_feeds(
+        app_state.books.clone(),
+        app_state.resubscribe.subscribe(),
+        tx,
+    ));
 
-            match connect_async(url.clone()).await {
-                Ok((mut ws_stream, _)) => {
-                    info!("WebSocket connected");
-                    let subscribe_msg = serde_json::json!({
-                        "method": "subscribe",
-                        "subscription": {
-                            "type": "l2Book",
-                            "coin": "@107",
-                            "nSigFigs": 5
-                        }
-                    });
-
-                    match ws_stream.send(tokio_tungstenite::tungstenite::Message::Text(subscribe_msg.to_string())).await {
-                        Ok(_) => {
-                            info!("Subscription message sent");
-                            retry_count = 0; // 接続成功したらリトライカウントをリセット
-                            
-                            while let Some(msg) = ws_stream.next().await {
-                                match msg {
-                                    Ok(msg) => {
-                                        match serde_json::from_str::<WsMessage>(&msg.to_string()) {
-                                            Ok(parsed) => {
-                                                if let Err(e) = tx.send(parsed).await {
-                                                    error!("Failed to send message through channel: {:?}", e);
-                                                    break;
-                                                }
-                                            }
-                                            Err(e) => {
-                                                warn!("Failed to parse WebSocket message: {:?}", e);
-                                                warn!("Message content: {}", msg.to_string());
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("WebSocket error: {:?}", e);
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to send subscription message: {:?}", e);
-                            retry_count += 1;
-                            continue;
-                        }
+    // 受信したデータを状態に反映する（描画はしない）
+    let pending = app_state.pending.clone();
+    tokio::spawn(async move {
+        info!("Starting data processing loop...");
+        while let Some((aggregation, received, msg)) = rx.recv().await {
+            let data = match msg {
+                WsMessage::L2Book(data) => data,
+                WsMessage::Trades(trades) => {
+                    // 約定は蓄積だけして、板の更新と一緒に描画する
+                    let Some(coin) = trades.first().map(|t| t.coin.clone()) else {
+                        continue;
+                    };
+                    let book = books.read().get(&coin).cloned();
+                    if let Some(book) = book {
+                        book.write().trades.push(&trades);
                     }
+                    continue;
                 }
-                Err(e) => {
-                    error!("Failed to connect WebSocket: {:?}", e);
-                    retry_count += 1;
+                WsMessage::SubscriptionResponse(response) => {
+                    info!("Subscription response: {}", response);
+                    continue;
                 }
-            }
-
-            info!("WebSocket connection lost. Reconnecting...");
-        }
-    });
+            };
 
-    // 受信したデータを処理
-    let handle = app_handle.clone();
-    tokio::spawn(async move {
-        info!("Starting data processing loop...");
-        while let Some(msg) = rx.recv().await {
-            let mut state = state.write();
+            // coinと集約レベルごとの状態に振り分ける（購読解除済みのメッセージは捨てる）
+            let Some(book) = books.read().get(&data.coin).cloned() else {
+                continue;
+            };
+            let mut book = book.write();
+            if book.is_stale(received) {
+                continue;
+            }
+            let Some(state) = book.level_mut(aggregation) else {
+                continue;
+            };
             
             // オーダーブックの更新
             state.buy.clear();  // 古いデータをクリア
             state.sell.clear();  // 古いデータをクリア
             
-            for (i, levels) in msg.data.levels.iter().enumerate() {
+            for (i, levels) in data.levels.iter().enumerate() {
                 for level in levels {
                     match (level.px.parse::<f64>(), level.sz.parse::<f64>()) {
                         (Ok(price), Ok(size)) => {
-                            info!("{}: {:?}", i, level);
+                            let level = Level { size, orders: level.n };
                             if i == 0 {
-                                state.buy.insert(OrderedFloat(price), size);
+                                state.buy.insert(OrderedFloat(price), level);
                             } else {
-                                state.sell.insert(OrderedFloat(price), size);
+                                state.sell.insert(OrderedFloat(price), level);
                             }
                         }
                         (Err(e), _) | (_, Err(e)) => {
@@ -185,179 +198,549 @@ async fn start_websocket_connection(app_handle: tauri::AppHandle) {
             }
 
             // 履歴の更新
-            state.update_history(msg.data.time);
+            state.update_history(data.time);
 
-            // ヒートマップの生成
-            let heatmap = generate_heatmap(&state);
-            
-            // フロントエンドにデータを送信
-            let payload = HeatmapData {
-                svg: heatmap
+            // 表示中の集約レベル（描画のときに選んだもの）が更新されたときだけ送信・描画する
+            let Some((visible, state)) = book.visible_level() else {
+                continue;
             };
-            
-            if let Err(e) = handle.emit("orderbook-update", payload) {
-                error!("Failed to emit event: {:?}", e);
+            if visible != aggregation {
+                continue;
             }
+
+            // バイナリの送信先には描画とは別に板そのものを送る（送れなくなった送信先は外す）
+            streams.lock().retain_mut(|stream| {
+                if stream.coin != data.coin {
+                    return true;
+                }
+                let frame = stream.encoder.next_frame(&data.coin, aggregation, data.time, state);
+                match stream.channel.send(InvokeResponseBody::Raw(frame.encode())) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("Closing book stream {} for {}: {:?}", stream.channel.id(), stream.coin, e);
+                        false
+                    }
+                }
+            });
+
+            // 描画は次のフレームでまとめて行う
+            pending.lock().mark(&data.coin);
         }
     });
+
+    // フレームクロックで描画
+    tokio::spawn(run_render_loop(app_handle, app_state));
 }
 
-fn generate_heatmap(state: &OrderBookState) -> String {
-    let mut document = Document::new()
-        .set("width", "100%")
-        .set("height", "100%")
-        .set("viewBox", format!("0 0 {} {}", HEATMAP_WIDTH, HEATMAP_HEIGHT))
-        .set("preserveAspectRatio", "xMidYMid meet");
+/// 銘柄の表示中の集約レベルを描画する
+/// 数値グリッドはそのまま送り、ヒートマップはPNGに変換して送れるよう板のロックを手放してから返す
+fn render_coin(handle: &tauri::AppHandle, (books, markets): (&RwLock<BookMap>, &RwLock<MarketCatalog>), canvases: &mut BTreeMap<String, ScrollingHeatmap>, coin: &str) -> Option<RenderedHeatmap> {
+    let Some(book) = books.read().get(coin).cloned() else {
+        canvases.remove(coin);
+        return None;
+    };
+    let market = markets.read().get(coin).cloned();
+    book.write().update_view(market.as_ref());
+    let book = book.read();
+    let (aggregation, state) = book.visible_level()?;
+    let view = book.view_range()?;
+
+    // 数値グリッドを求められている銘柄は描画せずにそのまま送る
+    if book.output == HeatmapOutput::Grid {
+        canvases.remove(coin);
+        let grid = HeatmapGrid::build(coin, aggregation, state, &render_style(&book, None), view)?;
+        if let Err(e) = handle.emit(&event_name(GRID_EVENT, coin), grid) {
+            error!("Failed to emit event: {:?}", e);
+        }
+        return None;
+    }
 
-    let max_size = state.history.iter()
-        .flat_map(|(_, buy, sell)| buy.values().chain(sell.values()))
-        .fold(0.0f64, |acc, &x| acc.max(x));
+    // ヒートマップの生成（価格軸の目盛りは表示範囲の上端での価格の刻みに揃える）
+    let tick_size = market.map(|market| market.tick_at(view.max_price));
+    let canvas = canvases.entry(coin.to_string()).or_insert_with(ScrollingHeatmap::new);
+    let heatmap = canvas.render(aggregation, state, &book.trades, &render_style(&book, tick_size), view)?;
+    Some(RenderedHeatmap {
+        coin: coin.to_string(),
+        aggregation,
+        range: view,
+        heatmap,
+    })
+}
 
-    if max_size <= 0.0 {
-        warn!("No data available for heatmap");
-        return document.to_string();
+/// 銘柄の設定から描画の見た目を組み立てる
+fn render_style(book: &CoinBook, tick_size: Option<f64>) -> RenderStyle<'_> {
+    RenderStyle {
+        metric: book.metric,
+        scale: book.scale,
+        colors: &book.colors,
+        time_axis: book.time_axis,
+        tick_size,
     }
+}
 
-    info!("Generating heatmap with max_size: {}", max_size);
-
-    // 背景を追加（ヒートマップ部分のみ）
-    let background = Rectangle::new()
-        .set("x", 0)
-        .set("y", 0)
-        .set("width", ACTUAL_HEATMAP_WIDTH)  // 余白を除いた幅
-        .set("height", "100%")
-        .set("fill", "#000000");
-    document = document.add(background);
-
-    // 全価格範囲を計算
-    let all_prices: Vec<f64> = state.history.iter()
-        .flat_map(|(_, buy, sell)| {
-            buy.keys().chain(sell.keys()).map(|k| k.into_inner())
-        })
-        .collect();
+/// 描画したヒートマップをPNGに変換してフロントエンドへ送る（時間がかかるのでブロッキング用のスレッドで呼ぶ）
+fn emit_heatmap(handle: &tauri::AppHandle, rendered: RenderedHeatmap) {
+    let RenderedHeatmap { coin, aggregation, range: ViewRange { since, until, min_price, max_price }, heatmap } = rendered;
+    let png = match heatmap.canvas.encode_png() {
+        Ok(png) => png,
+        Err(e) => {
+            error!("Failed to encode heatmap: {:?}", e);
+            return;
+        }
+    };
+
+    // フロントエンドにデータを送信
+    let payload = HeatmapData {
+        coin: coin.clone(),
+        aggregation,
+        since,
+        until,
+        min_price,
+        max_price,
+        image: format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png)),
+        overlay: heatmap.overlay
+    };
+
+    if let Err(e) = handle.emit(&event_name(UPDATE_EVENT, &coin), payload) {
+        error!("Failed to emit event: {:?}", e);
+    }
+}
+
+/// フレームクロックごとに、前のフレーム以降に更新のあった銘柄をまとめて描画する
+async fn run_render_loop(handle: tauri::AppHandle, app_state: AppState) {
+    info!("Starting render loop...");
+    // 銘柄ごとに描き足していくヒートマップ本体
+    let mut canvases: BTreeMap<String, ScrollingHeatmap> = BTreeMap::new();
+    let mut frame_rate = app_state.frame_rate.subscribe();
+    loop {
+        let fps = *frame_rate.borrow_and_update();
+        let period = frame_period(fps);
+        app_state.frame_stats.lock().fps = fps;
+
+        // 描画が間に合わなかったフレームは飛ばす（後からまとめて描画しない）
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                changed = frame_rate.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    info!("Frame rate changed to {} fps", *frame_rate.borrow());
+                    break;
+                }
+            }
 
-    let min_price = all_prices.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-    let max_price = all_prices.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-    let price_range = max_price - min_price;
-
-    info!("Price range: {} to {} (width: {})", min_price, max_price, price_range);
-
-    // 時系列データを描画（x座標を調整）
-    if let Some(latest_ts) = state.history.last().map(|(ts, _, _)| ts) {
-        let five_minutes_ago = latest_ts - 300_000;
-
-        for (_i, (ts, buy, sell)) in state.history.iter().enumerate() {
-            let time_x = ((ts - five_minutes_ago) as f64 / 300_000.0 * ACTUAL_HEATMAP_WIDTH as f64) as i32;
-            let bar_width = (ACTUAL_HEATMAP_WIDTH as f64 / state.history.len() as f64).ceil() as i32 + 1;
-
-            for (&price, &size) in sell.iter().rev() {
-                let price_val = price.into_inner();
-                let y = HEATMAP_HEIGHT as i32 - ((price_val - min_price) / price_range * HEATMAP_HEIGHT as f64) as i32;
-                let alpha = (size / max_size).min(1.0);
-                
-                let rect = Rectangle::new()
-                    .set("x", time_x)
-                    .set("y", y)
-                    .set("width", bar_width)
-                    .set("height", 2)
-                    .set("fill", format!("rgba(255, 0, 0, {})", alpha));
-
-                document = document.add(rect);
+            // 購読をやめた銘柄は二度と描画待ちにならないので、ここでヒートマップ本体を手放す
+            {
+                let books = app_state.books.read();
+                canvases.retain(|coin, _| books.contains_key(coin));
             }
 
-            for (&price, &size) in buy.iter() {
-                let price_val = price.into_inner();
-                let y = HEATMAP_HEIGHT as i32 - ((price_val - min_price) / price_range * HEATMAP_HEIGHT as f64) as i32;
-                let alpha = (size / max_size).min(1.0);
-                
-                let rect = Rectangle::new()
-                    .set("x", time_x)
-                    .set("y", y)
-                    .set("width", bar_width)
-                    .set("height", 2)
-                    .set("fill", format!("rgba(0, 255, 0, {})", alpha));
-
-                document = document.add(rect);
+            let pending = app_state.pending.lock().take();
+            if pending.is_empty() {
+                continue;
+            }
+            let started = Instant::now();
+            // PNGへの変換は銘柄ごとにブロッキング用のスレッドで行い、すべて送り終えるまでを1フレームとする
+            let mut encoding = Vec::new();
+            for coin in pending.keys() {
+                if let Some(rendered) = render_coin(&handle, (&app_state.books, &app_state.markets), &mut canvases, coin) {
+                    let handle = handle.clone();
+                    encoding.push(tokio::task::spawn_blocking(move || emit_heatmap(&handle, rendered)));
+                }
+            }
+            for task in encoding {
+                if let Err(e) = task.await {
+                    error!("Failed to encode heatmap: {:?}", e);
+                }
+            }
+            let elapsed = started.elapsed();
+            let dropped = app_state.frame_stats.lock().record(&pending, elapsed, period);
+            if dropped > 0 {
+                warn!("Rendering took {:?}, dropped {} frame(s)", elapsed, dropped);
             }
+        }
+    }
+}
+
+/// 銘柄カタログを取得してAppStateに格納する
+async fn load_markets(state: &AppState) -> Result<(), String> {
+    let catalog = MarketCatalog::fetch().await.map_err(|e| {
+        error!("Failed to fetch market catalog: {:?}", e);
+        e.to_string()
+    })?;
+    info!("Loaded {} markets", catalog.markets().len());
+    *state.markets.write() = catalog;
+    Ok(())
+}
 
-            // その時点でのMid価格を描画
-            let best_buy = buy.keys().next_back().map(|x| x.into_inner()).unwrap_or(0.0);
-            let best_sell = sell.keys().next().map(|x| x.into_inner()).unwrap_or(0.0);
-            let mid = (best_buy + best_sell) / 2.0;
-
-            // Mid Line (白)
-            let mid_y = HEATMAP_HEIGHT as i32 - ((mid - min_price) / price_range * HEATMAP_HEIGHT as f64) as i32;
-            let mid_line = Rectangle::new()
-                .set("x", time_x)
-                .set("y", mid_y)
-                .set("width", bar_width)
-                .set("height", 1)
-                .set("fill", "rgba(255, 255, 255, 0.8)");
-            document = document.add(mid_line);
+/// 入力された銘柄名を購読用のcoinに正規化する
+fn resolve_coin(coin: &str, state: &AppState) -> Result<String, String> {
+    let coin = coin.trim();
+    if coin.is_empty() {
+        return Err("coin must not be empty".to_string());
+    }
+    // 表示用シンボルが指定された場合は購読用のcoinに変換する
+    let resolved = state.markets.read().resolve(coin).map(|m| m.coin.clone());
+    Ok(resolved.unwrap_or_else(|| coin.to_string()))
+}
+
+/// 監視中の銘柄をすべて外し、指定した銘柄だけを購読する（例: "BTC", "ETH", "@107", "HYPE/USDC"）
+#[tauri::command]
+fn set_coin(coin: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    {
+        let mut books = state.books.write();
+        if books.len() == 1 && books.contains_key(&coin) {
+            return Ok(());
         }
+        info!("Switching coins: {:?} -> {}", books.keys().collect::<Vec<_>>(), coin);
+        books.clear();
+        books.insert(coin, Arc::new(RwLock::new(CoinBook::new(AggregationMode::default()))));
     }
+    state.notify_resubscribe();
+    Ok(())
+}
 
-    // 価格軸のグループを作成
-    let mut price_axis_group = Group::new()
-        .set("font-family", "Arial")
-        .set("font-size", "14")
-        .set("fill", "white");
-
-    // 価格軸の目盛りを生成（10分割）
-    let price_steps = 10;
-    for i in 0..=price_steps {
-        let price = min_price + (price_range * i as f64 / price_steps as f64);
-        let y = (HEATMAP_HEIGHT as f64 * (1.0 - i as f64 / price_steps as f64)) as i32;
-        
-        // 価格ラベル
-        let price_text = Text::new()
-            .set("x", ACTUAL_HEATMAP_WIDTH + 20)  // ヒートマップの右側に配置
-            .set("y", y + 5)
-            .set("text-anchor", "start")
-            .add(TextNode::new(format!("{:.3}", price)));
-        
-        // 目盛り線
-        let tick_line = Line::new()
-            .set("x1", ACTUAL_HEATMAP_WIDTH)  // ヒートマップの終端から開始
-            .set("x2", ACTUAL_HEATMAP_WIDTH + 10)  // 少し右に伸ばす
-            .set("y1", y)
-            .set("y2", y)
-            .set("stroke", "white")
-            .set("stroke-width", 1);
-
-        price_axis_group = price_axis_group.add(price_text).add(tick_line);
+/// 監視する銘柄を追加する
+#[tauri::command]
+fn add_coin(coin: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    {
+        let mut books = state.books.write();
+        if books.contains_key(&coin) {
+            return Ok(());
+        }
+        info!("Adding coin: {}", coin);
+        books.insert(coin, Arc::new(RwLock::new(CoinBook::new(AggregationMode::default()))));
     }
+    state.notify_resubscribe();
+    Ok(())
+}
 
-    // // 最大注文量の表示位置を調整
-    // if let (Some((&large_buy_price, &large_buy_size)), Some((&large_sell_price, &large_sell_size))) = (
-    //     state.buy.iter().max_by_key(|(_, &size)| OrderedFloat(size)),
-    //     state.sell.iter().max_by_key(|(_, &size)| OrderedFloat(size))
-    // ) {
-    //     // 最大Bid注文
-    //     let max_bid_y = ((max_bid_price.into_inner() - min_price) / price_range * HEATMAP_HEIGHT as f64) as i32;
-    //     let max_bid_text = Text::new()
-    //         .set("x", ACTUAL_HEATMAP_WIDTH + 80)  // ヒートマップの右側に配置
-    //         .set("y", max_bid_y)
-    //         .set("text-anchor", "start")  // 左揃えに変更
-    //         .set("fill", "red")
-    //         .add(TextNode::new(format!("Large Bid: {:.3} ({:.2})", max_bid_price.into_inner(), max_bid_size)));
-
-    //     // 最大Ask注文
-    //     let max_ask_y = ((max_ask_price.into_inner() - min_price) / price_range * HEATMAP_HEIGHT as f64) as i32;
-    //     let max_ask_text = Text::new()
-    //         .set("x", ACTUAL_HEATMAP_WIDTH + 80)  // ヒートマップの右側に配置
-    //         .set("y", max_ask_y)
-    //         .set("text-anchor", "start")  // 左揃えに変更
-    //         .set("fill", "green")
-    //         .add(TextNode::new(format!("Large Ask: {:.3} ({:.2})", max_ask_price.into_inner(), max_ask_size)));
-
-    //     price_axis_group = price_axis_group.add(max_bid_text).add(max_ask_text);
-    // }
-
-    // 価格軸グループをドキュメントに追加
-    document = document.add(price_axis_group);
-
-    document.to_string()
+/// 監視している銘柄を外す（状態と履歴も破棄する）
+#[tauri::command]
+fn remove_coin(coin: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    if state.books.write().remove(&coin).is_none() {
+        return Err(format!("{} is not subscribed", coin));
+    }
+    info!("Removed coin: {}", coin);
+    state.notify_resubscribe();
+    Ok(())
+}
+
+/// 銘柄の価格集約を変更する（再購読し、履歴はリセットされる）
+#[tauri::command]
+fn set_aggregation(coin: String, mode: AggregationMode, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    mode.validate()?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    {
+        let mut book = book.write();
+        if book.mode == mode {
+            return Ok(());
+        }
+        info!("Changing aggregation of {}: {:?} -> {:?}", coin, book.mode, mode);
+        book.set_mode(mode);
+    }
+    state.notify_resubscribe();
+    Ok(())
+}
+
+/// 表示する価格範囲の決め方（自動・Mid中心・固定）を切り替える
+#[tauri::command]
+fn set_viewport(coin: String, viewport: PriceViewport, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    viewport.validate()?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().set_viewport(viewport);
+    Ok(())
+}
+
+/// ヒートマップ上の位置 (x, y)（viewBox の座標）を中心に、表示範囲を factor 倍にする（1.0より大きければ広く表示）
+/// 本体の外の位置は最も近い本体の端として扱う
+/// 最新に追従しているときに右端付近より過去を指すと、その位置を保つために右端を固定する
+#[tauri::command]
+fn zoom_viewport(coin: String, x: f64, y: f64, factor: f64, axes: ViewAxes, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(format!("invalid zoom factor: {}", factor));
+    }
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    let mut book = book.write();
+    let Some(point) = book.view_range().and_then(|range| heatmap::clamped_point_at((x, y), range)) else {
+        return Err(format!("no order book data for {}", coin));
+    };
+    book.zoom(point, factor, axes);
+    Ok(())
+}
+
+/// 表示範囲を画面上で (dx, dy)（viewBox の座標）だけ動かした分ずらす
+/// ドラッグでは内容がポインタに付いてくるよう、ポインタの移動量の符号を反転して渡す
+#[tauri::command]
+fn pan_viewport(coin: String, dx: f64, dy: f64, axes: ViewAxes, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    if !(dx.is_finite() && dy.is_finite()) {
+        return Err(format!("invalid pan distance: {}, {}", dx, dy));
+    }
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    let mut book = book.write();
+    let Some(range) = book.view_range() else {
+        return Err(format!("no order book data for {}", coin));
+    };
+    book.pan(heatmap::delta_at((dx, dy), range), axes);
+    Ok(())
+}
+
+/// 拡大・移動を解除して、保持期間全体・自動の価格範囲に戻す
+#[tauri::command]
+fn reset_viewport(coin: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().reset_view();
+    Ok(())
+}
+
+/// ヒートマップの濃淡に使う量を切り替える
+#[tauri::command]
+fn set_heatmap_metric(coin: String, metric: HeatmapMetric, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().metric = metric;
+    Ok(())
+}
+
+/// ヒートマップの濃淡の付け方（曲線と基準にするパーセンタイル）を切り替える
+#[tauri::command]
+fn set_intensity_scale(coin: String, scale: IntensityScale, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    scale.validate()?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().scale = scale;
+    Ok(())
+}
+
+/// ヒートマップの配色（グラデーションとBid/Askを区別するか）を切り替える
+#[tauri::command]
+fn set_color_map(coin: String, colors: ColorMap, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    colors.validate()?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().colors = colors;
+    Ok(())
+}
+
+/// 時刻軸のタイムゾーン（ローカル/UTC）と補助線の有無を切り替える
+#[tauri::command]
+fn set_time_axis(coin: String, axis: TimeAxis, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().time_axis = axis;
+    Ok(())
+}
+
+/// 表示する時間幅（30秒〜6時間、ミリ秒）を変更する
+#[tauri::command]
+fn set_time_window(coin: String, window_ms: i64, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    validate_window(window_ms)?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().set_window(window_ms);
+    Ok(())
+}
+
+/// 古い履歴を集計した区間を、最大・平均・最後のどの値で描くかを切り替える
+#[tauri::command]
+fn set_bucket_stat(coin: String, stat: BucketStat, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().set_stat(stat);
+    Ok(())
+}
+
+/// 1銘柄の履歴に使うメモリの上限（MB）を変更する
+#[tauri::command]
+fn set_history_limit(coin: String, limit_mb: usize, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    validate_history_limit(limit_mb)?;
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().set_history_limit(limit_mb);
+    Ok(())
+}
+
+/// 板の更新をバイナリ形式（wire.rs）で送るチャンネルを登録する
+/// 最初にスナップショット、以降は差分を送る
+#[tauri::command]
+fn stream_book(coin: String, channel: Channel, state: tauri::State<'_, AppState>) -> Result<u32, String> {
+    let coin = resolve_coin(&coin, &state)?;
+    if !state.books.read().contains_key(&coin) {
+        return Err(format!("{} is not subscribed", coin));
+    }
+    let id = channel.id();
+    info!("Streaming {} to channel {}", coin, id);
+    state.streams.lock().push(BookStream {
+        coin,
+        channel,
+        encoder: BookEncoder::default(),
+    });
+    Ok(id)
+}
+
+/// stream_book で登録したチャンネルへの送信をやめる
+#[tauri::command]
+fn stop_book_stream(id: u32, state: tauri::State<'_, AppState>) {
+    state.streams.lock().retain(|stream| stream.channel.id() != id);
+}
+
+/// 描画のフレームレートを変更する（1〜30fps）
+#[tauri::command]
+fn set_frame_rate(fps: u32, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    validate_frame_rate(fps)?;
+    state.frame_rate.send_if_modified(|current| std::mem::replace(current, fps) != fps);
+    Ok(())
+}
+
+/// 描画の統計（描画・スキップしたフレーム数など）を返す
+#[tauri::command]
+fn get_frame_stats(state: tauri::State<'_, AppState>) -> FrameStats {
+    state.frame_stats.lock().clone()
+}
+
+/// 表示中のヒートマップ全体をSVGとして書き出す
+#[tauri::command]
+fn export_svg(coin: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    let book = book.read();
+    let (Some((_, level)), Some(view)) = (book.visible_level(), book.view_range()) else {
+        return Err(format!("no order book data for {}", coin));
+    };
+    let tick_size = state.markets.read().get(&coin).map(|market| market.tick_at(view.max_price));
+    Ok(heatmap::render_svg(level, &book.trades, &render_style(&book, tick_size), view))
+}
+
+/// ヒートマップ上の位置の板の状態を履歴から返す（本体の外や履歴より前の時刻なら None）
+#[tauri::command]
+fn get_book_readout(coin: String, point: ReadoutPoint, state: tauri::State<'_, AppState>) -> Result<Option<BookReadout>, String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    let book = book.read();
+    let (Some((aggregation, level)), Some(range)) = (book.visible_level(), book.view_range()) else {
+        return Ok(None);
+    };
+    let point = match point {
+        ReadoutPoint::Pixel { x, y } => match heatmap::point_at((x, y), range) {
+            Some(point) => point,
+            None => return Ok(None),
+        },
+        ReadoutPoint::Point { time, price } => (time, price),
+    };
+    // 価格レベルは2画素の高さで描くので、その分と価格レベルの間隔の半分のうち広い方まで近くのレベルとみなす
+    let pixels = heatmap::delta_at((0.0, 2.0), range).1.abs();
+    let tolerance = level.price_gap().map_or(pixels, |gap| (gap / 2.0).max(pixels));
+    Ok(BookReadout::at(aggregation, level, point, tolerance))
+}
+
+/// フロントエンドへ送るデータを画像と数値グリッドで切り替える
+#[tauri::command]
+fn set_heatmap_output(coin: String, output: HeatmapOutput, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let coin = resolve_coin(&coin, &state)?;
+    let Some(book) = state.books.read().get(&coin).cloned() else {
+        return Err(format!("{} is not subscribed", coin));
+    };
+    book.write().output = output;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CoinInfo {
+    coin: String,
+    event: String,
+    grid_event: String,
+    mode: AggregationMode,
+    metric: HeatmapMetric,
+    scale: IntensityScale,
+    colors: ColorMap,
+    time_axis: TimeAxis,
+    viewport: PriceViewport,
+    time_viewport: TimeViewport,
+    window_ms: i64,
+    stat: BucketStat,
+    history_limit_mb: usize,
+    // 履歴が使っているメモリの概算（バイト）
+    history_bytes: usize,
+    output: HeatmapOutput,
+}
+
+/// 監視している銘柄と、そのヒートマップのイベント名・集約を返す
+#[tauri::command]
+fn get_coins(state: tauri::State<'_, AppState>) -> Vec<CoinInfo> {
+    state
+        .books
+        .read()
+        .iter()
+        .map(|(coin, book)| {
+            let book = book.read();
+            CoinInfo {
+                coin: coin.clone(),
+                event: event_name(UPDATE_EVENT, coin),
+                grid_event: event_name(GRID_EVENT, coin),
+                mode: book.mode,
+                metric: book.metric,
+                scale: book.scale,
+                colors: book.colors.clone(),
+                time_axis: book.time_axis,
+                viewport: book.viewport(),
+                time_viewport: book.time_viewport(),
+                window_ms: book.window(),
+                stat: book.stat(),
+                history_limit_mb: book.history_limit_mb(),
+                history_bytes: book.history_bytes(),
+                output: book.output,
+            }
+        })
+        .collect()
+}
+
+/// 銘柄ピッカー用の一覧を返す（未取得ならその場で取得する）
+#[tauri::command]
+async fn list_markets(state: tauri::State<'_, AppState>) -> Result<Vec<Market>, String> {
+    if state.markets.read().is_empty() {
+        load_markets(&state).await?;
+    }
+    Ok(state.markets.read().markets().to_vec())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -378,13 +761,47 @@ pub fn run() {
         .block_on(async {
             tauri::Builder::default()
                 .plugin(tauri_plugin_opener::init())
+                .invoke_handler(tauri::generate_handler![
+                    set_coin,
+                    add_coin,
+                    remove_coin,
+                    get_coins,
+                    set_aggregation,
+                    set_viewport,
+                    zoom_viewport,
+                    pan_viewport,
+                    reset_viewport,
+                    set_heatmap_metric,
+                    set_intensity_scale,
+                    set_color_map,
+                    set_time_axis,
+                    set_time_window,
+                    set_bucket_stat,
+                    set_history_limit,
+                    set_heatmap_output,
+                    stream_book,
+                    stop_book_stream,
+                    set_frame_rate,
+                    get_frame_stats,
+                    export_svg,
+                    get_book_readout,
+                    list_markets
+                ])
                 .setup(|app| {
                     info!("Setting up application...");
                     let handle = app.handle().clone();
+                    let app_state = AppState::new();
+                    app.manage(app_state.clone());
+
+                    // 銘柄カタログを取得
+                    let catalog_state = app_state.clone();
+                    tokio::spawn(async move {
+                        let _ = load_markets(&catalog_state).await;
+                    });
                     
                     // WebSocket接続を開始
                     tokio::spawn(async move {
-                        start_websocket_connection(handle).await;
+                        start_websocket_connection(handle, app_state).await;
                     });
                     
                     Ok(())
@@ -0,0 +1,225 @@
+// Hyperliquidの銘柄カタログ（info APIの meta / spotMeta から構築）
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const INFO_URL: &str = "https://api.hyperliquid.xyz/info";
+// 価格の最大小数桁数（実際の上限は MAX_DECIMALS - szDecimals）
+const PERP_MAX_DECIMALS: u32 = 6;
+const SPOT_MAX_DECIMALS: u32 = 8;
+// 価格の有効数字の上限（整数の価格はこれを超えてもよい）
+const MAX_SIGNIFICANT_FIGURES: i32 = 5;
+
+#[derive(Debug, Deserialize)]
+struct PerpAsset {
+    name: String,
+    #[serde(rename = "szDecimals")]
+    sz_decimals: u32,
+    #[serde(rename = "isDelisted", default)]
+    is_delisted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PerpMeta {
+    universe: Vec<PerpAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpotToken {
+    name: String,
+    #[serde(rename = "szDecimals")]
+    sz_decimals: u32,
+    index: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpotPair {
+    name: String,
+    tokens: [u32; 2],
+}
+
+#[derive(Debug, Deserialize)]
+struct SpotMeta {
+    tokens: Vec<SpotToken>,
+    universe: Vec<SpotPair>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketKind {
+    Perp,
+    Spot,
+}
+
+/// 購読に使うcoin（"BTC" や "@107"）と表示用シンボル（"HYPE/USDC"）の対応
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Market {
+    pub coin: String,
+    pub symbol: String,
+    pub kind: MarketKind,
+    pub sz_decimals: u32,
+    pub price_decimals: u32,
+    // 価格の最小刻み（10^-price_decimals）
+    pub tick_size: f64,
+}
+
+impl Market {
+    fn new(coin: &str, symbol: String, kind: MarketKind, sz_decimals: u32) -> Self {
+        let max_decimals = match kind {
+            MarketKind::Perp => PERP_MAX_DECIMALS,
+            MarketKind::Spot => SPOT_MAX_DECIMALS,
+        };
+        let price_decimals = max_decimals.saturating_sub(sz_decimals);
+        Self {
+            coin: coin.to_string(),
+            symbol,
+            kind,
+            sz_decimals,
+            price_decimals,
+            tick_size: 10f64.powi(-(price_decimals as i32)),
+        }
+    }
+
+    /// price 付近で実際に使われる価格の刻み（小数桁数と有効数字の両方の上限に従う）
+    pub fn tick_at(&self, price: f64) -> f64 {
+        if !(price > 0.0 && price.is_finite()) {
+            return self.tick_size;
+        }
+        let significant = 10f64.powi(price.log10().floor() as i32 + 1 - MAX_SIGNIFICANT_FIGURES);
+        significant.min(1.0).max(self.tick_size)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MarketCatalog {
+    markets: Vec<Market>,
+}
+
+impl MarketCatalog {
+    /// info APIから perp と spot の銘柄一覧を取得する
+    pub async fn fetch() -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::new();
+        let meta: PerpMeta = client
+            .post(INFO_URL)
+            .json(&serde_json::json!({ "type": "meta" }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let spot_meta: SpotMeta = client
+            .post(INFO_URL)
+            .json(&serde_json::json!({ "type": "spotMeta" }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Self::from_meta(&meta, &spot_meta))
+    }
+
+    fn from_meta(meta: &PerpMeta, spot_meta: &SpotMeta) -> Self {
+        let mut markets: Vec<Market> = meta
+            .universe
+            .iter()
+            .filter(|asset| !asset.is_delisted)
+            .map(|asset| Market::new(&asset.name, asset.name.clone(), MarketKind::Perp, asset.sz_decimals))
+            .collect();
+
+        // spotのペアはトークンのindexで参照される（配列の位置ではない）
+        let tokens: HashMap<u32, &SpotToken> = spot_meta.tokens.iter().map(|t| (t.index, t)).collect();
+        for pair in &spot_meta.universe {
+            let (Some(base), Some(quote)) = (tokens.get(&pair.tokens[0]), tokens.get(&pair.tokens[1])) else {
+                warn!("Unknown token in spot pair {}: {:?}", pair.name, pair.tokens);
+                continue;
+            };
+            let symbol = format!("{}/{}", base.name, quote.name);
+            markets.push(Market::new(&pair.name, symbol, MarketKind::Spot, base.sz_decimals));
+        }
+
+        Self { markets }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.is_empty()
+    }
+
+    pub fn markets(&self) -> &[Market] {
+        &self.markets
+    }
+
+    pub fn get(&self, coin: &str) -> Option<&Market> {
+        self.markets.iter().find(|m| m.coin == coin)
+    }
+
+    /// coin または表示用シンボルから銘柄を探す（大文字小文字は区別しない）
+    pub fn resolve(&self, name: &str) -> Option<&Market> {
+        self.get(name).or_else(|| {
+            self.markets
+                .iter()
+                .find(|m| m.symbol.eq_ignore_ascii_case(name) || m.coin.eq_ignore_ascii_case(name))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> MarketCatalog {
+        let meta: PerpMeta = serde_json::from_str(include_str!("../fixtures/meta.json")).unwrap();
+        let spot_meta: SpotMeta = serde_json::from_str(include_str!("../fixtures/spot_meta.json")).unwrap();
+        MarketCatalog::from_meta(&meta, &spot_meta)
+    }
+
+    #[test]
+    fn maps_spot_index_to_symbol() {
+        let catalog = catalog();
+        let hype = catalog.get("@107").unwrap();
+        assert_eq!(hype.symbol, "HYPE/USDC");
+        assert_eq!(hype.kind, MarketKind::Spot);
+        assert_eq!(hype.sz_decimals, 2);
+        assert_eq!(hype.price_decimals, 6);
+        assert_eq!(catalog.get("PURR/USDC").unwrap().symbol, "PURR/USDC");
+    }
+
+    #[test]
+    fn skips_delisted_perps() {
+        let catalog = catalog();
+        assert!(catalog.get("MATIC").is_none());
+        let btc = catalog.get("BTC").unwrap();
+        assert_eq!(btc.kind, MarketKind::Perp);
+        assert_eq!(btc.price_decimals, 1);
+    }
+
+    #[test]
+    fn resolves_symbol_and_coin() {
+        let catalog = catalog();
+        assert_eq!(catalog.resolve("hype/usdc").unwrap().coin, "@107");
+        assert_eq!(catalog.resolve("HYPE").unwrap().kind, MarketKind::Perp);
+        assert_eq!(catalog.resolve("kpepe").unwrap().coin, "kPEPE");
+        assert!(catalog.resolve("DOGE").is_none());
+    }
+
+    #[test]
+    fn tick_size_follows_price_decimals() {
+        let catalog = catalog();
+        assert!((catalog.get("BTC").unwrap().tick_size - 0.1).abs() < 1e-12);
+        assert!((catalog.get("@107").unwrap().tick_size - 0.000001).abs() < 1e-15);
+        assert_eq!(catalog.get("PURR/USDC").unwrap().price_decimals, 8);
+    }
+
+    #[test]
+    fn tick_follows_significant_figures() {
+        let catalog = catalog();
+        let btc = catalog.get("BTC").unwrap();
+        // 有効数字5桁を超えても、整数の価格は使える
+        assert_eq!(btc.tick_at(105_000.0), 1.0);
+        assert_eq!(btc.tick_at(65_000.0), 1.0);
+        assert!((btc.tick_at(2_500.0) - 0.1).abs() < 1e-12);
+        let hype = catalog.get("@107").unwrap();
+        assert!((hype.tick_at(25.0) - 0.001).abs() < 1e-12);
+        assert!((hype.tick_at(0.00001234) - 0.000001).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,201 @@
+// 価格帯別出来高（ボリュームプロファイル）
+use crate::book::{OrderBookState, TradeWindow};
+use crate::feed::Side;
+use svg::node::element::{Group, Line, Rectangle, Text};
+use svg::node::Text as TextNode;
+
+const PROFILE_BINS: usize = 180;  // 価格方向の分割数
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSource {
+    // 約定から集計（買い/売りはテイカー側）
+    Trades,
+    // 約定がない場合は板に並んでいた数量の時間平均から集計（買い/売りはBid/Ask）
+    Liquidity,
+}
+
+pub struct VolumeProfile {
+    pub source: ProfileSource,
+    // 価格の低い順の (買い, 売り) 数量
+    pub bins: Vec<(f64, f64)>,
+}
+
+impl VolumeProfile {
+    /// 表示中の時間・価格範囲から出来高を集計する
+    pub fn build(
+        state: &OrderBookState,
+        trades: &TradeWindow,
+        (since, until): (i64, i64),
+        (min_price, max_price): (f64, f64),
+    ) -> Option<Self> {
+        let price_range = max_price - min_price;
+        if price_range <= 0.0 {
+            return None;
+        }
+        let bin_of = |price: f64| -> Option<usize> {
+            if price < min_price || price > max_price {
+                return None;
+            }
+            Some((((price - min_price) / price_range * PROFILE_BINS as f64) as usize).min(PROFILE_BINS - 1))
+        };
+
+        let mut bins = vec![(0.0, 0.0); PROFILE_BINS];
+        let mut source = ProfileSource::Trades;
+        for trade in trades.iter().filter(|t| t.time > since && t.time <= until) {
+            if let Some(i) = bin_of(trade.price) {
+                match trade.side {
+                    Side::Buy => bins[i].0 += trade.size,
+                    Side::Sell => bins[i].1 += trade.size,
+                }
+            }
+        }
+
+        if bins.iter().all(|&(buy, sell)| buy + sell <= 0.0) {
+            source = ProfileSource::Liquidity;
+            // 各スナップショットは次のスナップショットまで（最新のものは until まで）の板を表すので、
+            // 範囲内に続いた時間で重み付けする（更新が集中した時間帯に偏らないように）
+            let history = state.history_between((since, until));
+            let span = (until - since) as f64;
+            for (i, (ts, buy, sell)) in history.iter().enumerate() {
+                let end = history.get(i + 1).map_or(until, |(next, _, _)| *next).min(until);
+                let weight = (end - (*ts).max(since)) as f64 / span;
+                if weight <= 0.0 {
+                    continue;
+                }
+                for (price, level) in buy.iter() {
+                    if let Some(i) = bin_of(price.into_inner()) {
+                        bins[i].0 += level.size * weight;
+                    }
+                }
+                for (price, level) in sell.iter() {
+                    if let Some(i) = bin_of(price.into_inner()) {
+                        bins[i].1 += level.size * weight;
+                    }
+                }
+            }
+        }
+
+        if bins.iter().all(|&(buy, sell)| buy + sell <= 0.0) {
+            return None;
+        }
+        Some(Self { source, bins })
+    }
+
+    /// 出来高が最大の価格帯（POC）
+    pub fn point_of_control(&self) -> Option<usize> {
+        self.bins
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| (a.0 + a.1).total_cmp(&(b.0 + b.1)))
+            .map(|(i, _)| i)
+    }
+
+    /// x から幅 width、高さ height の領域にヒストグラムを描画する
+    pub fn render(&self, x: i32, width: i32, height: i32) -> Group {
+        let mut group = Group::new();
+        let max_volume = self.bins.iter().map(|(buy, sell)| buy + sell).fold(0.0f64, f64::max);
+        if max_volume <= 0.0 {
+            return group;
+        }
+        let bin_height = height as f64 / self.bins.len() as f64;
+        let (buy_color, sell_color) = match self.source {
+            ProfileSource::Trades => ("rgba(0, 255, 128, 0.7)", "rgba(255, 64, 64, 0.7)"),
+            ProfileSource::Liquidity => ("rgba(0, 255, 0, 0.35)", "rgba(255, 0, 0, 0.35)"),
+        };
+
+        for (i, &(buy, sell)) in self.bins.iter().enumerate() {
+            let y = height as f64 - (i + 1) as f64 * bin_height;
+            let buy_width = buy / max_volume * width as f64;
+            let sell_width = sell / max_volume * width as f64;
+
+            // 買い→売りの順に左から積み上げる
+            if buy > 0.0 {
+                group = group.add(Rectangle::new()
+                    .set("x", x)
+                    .set("y", y)
+                    .set("width", buy_width)
+                    .set("height", bin_height)
+                    .set("fill", buy_color));
+            }
+            if sell > 0.0 {
+                group = group.add(Rectangle::new()
+                    .set("x", x as f64 + buy_width)
+                    .set("y", y)
+                    .set("width", sell_width)
+                    .set("height", bin_height)
+                    .set("fill", sell_color));
+            }
+        }
+
+        // POCを黄色の線で示す
+        if let Some(poc) = self.point_of_control() {
+            let y = height as f64 - (poc as f64 + 0.5) * bin_height;
+            group = group
+                .add(Line::new()
+                    .set("x1", x)
+                    .set("x2", x + width)
+                    .set("y1", y)
+                    .set("y2", y)
+                    .set("stroke", "yellow")
+                    .set("stroke-width", 1)
+                    .set("stroke-dasharray", "4 2"))
+                .add(Text::new()
+                    .set("x", x + width)
+                    .set("y", y - 4.0)
+                    .set("text-anchor", "end")
+                    .set("font-family", "Arial")
+                    .set("font-size", "12")
+                    .set("fill", "yellow")
+                    .add(TextNode::new("POC")));
+        }
+
+        group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{side, trade};
+
+    #[test]
+    fn bins_trades_and_finds_point_of_control() {
+        let mut trades = TradeWindow::new(60_000);
+        trades.push(&[
+            trade(1, 1_000, Side::Buy, 10.2, 2.0),
+            trade(2, 2_000, Side::Sell, 10.7, 3.0),
+            trade(3, 3_000, Side::Buy, 100.5, 4.0),
+            // 価格範囲・時間範囲の外の約定は数えない
+            trade(4, 3_000, Side::Buy, 200.0, 9.0),
+            trade(5, 9_000, Side::Sell, 100.5, 9.0),
+        ]);
+        // 1価格帯 = 1.0
+        let profile = VolumeProfile::build(&OrderBookState::new(), &trades, (0, 5_000), (0.0, 180.0)).unwrap();
+        assert_eq!(profile.source, ProfileSource::Trades);
+        assert_eq!(profile.bins[10], (2.0, 3.0));
+        assert_eq!(profile.bins[100], (4.0, 0.0));
+        assert_eq!(profile.bins.iter().map(|(buy, sell)| buy + sell).sum::<f64>(), 9.0);
+        // 買いと売りを合わせて最大の価格帯
+        assert_eq!(profile.point_of_control(), Some(10));
+    }
+
+    #[test]
+    fn empty_without_trades_or_book() {
+        let trades = TradeWindow::new(60_000);
+        assert!(VolumeProfile::build(&OrderBookState::new(), &trades, (0, 5_000), (0.0, 180.0)).is_none());
+        assert!(VolumeProfile::build(&OrderBookState::new(), &trades, (0, 5_000), (1.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn liquidity_is_weighted_by_duration() {
+        let mut state = OrderBookState::new();
+        // 数量4の板が1秒、数量1の板が残りの3秒続いた（更新の回数は後者の方が多い）
+        for (ts, size) in [(1_000, 4.0), (2_000, 1.0), (3_000, 1.0), (4_000, 1.0)] {
+            state.buy = side(&[(10.5, size, 1)]);
+            state.update_history(ts);
+        }
+        let profile = VolumeProfile::build(&state, &TradeWindow::new(60_000), (1_000, 5_000), (0.0, 180.0)).unwrap();
+        assert_eq!(profile.source, ProfileSource::Liquidity);
+        assert_eq!(profile.bins[10], ((4.0 * 1.0 + 1.0 * 3.0) / 4.0, 0.0));
+    }
+}
@@ -0,0 +1,150 @@
+// RGBAピクセルバッファへの描画
+// セルごとにSVG要素を作るとDOMが数万ノードになるため、ヒートマップ本体はここに直接描く
+
+/// 8bitのRGBと不透明度（0.0〜1.0）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f64,
+}
+
+impl Color {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: f64) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub fn with_alpha(self, a: f64) -> Self {
+        Self { a, ..self }
+    }
+
+    /// "#rrggbb" または "#rrggbbaa" を読む
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+        let a = if digits.len() == 8 { channel(6)? as f64 / 255.0 } else { 1.0 };
+        Some(Self::rgba(channel(0)?, channel(2)?, channel(4)?, a))
+    }
+
+    /// SVGのfill/strokeに指定する表記
+    pub fn css(&self) -> String {
+        format!("rgba({}, {}, {}, {})", self.r, self.g, self.b, self.a)
+    }
+}
+
+/// 左上原点のRGBA（各8bit、アルファは非乗算）画像
+#[derive(PartialEq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// 透明で初期化する
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// 全体を左へ columns 列ずらす（右端に空いた列は透明になる）
+    pub fn scroll_left(&mut self, columns: u32) {
+        let stride = self.width as usize * 4;
+        let shift = columns.min(self.width) as usize * 4;
+        for row in self.pixels.chunks_exact_mut(stride) {
+            row.copy_within(shift.., 0);
+            row[stride - shift..].fill(0);
+        }
+    }
+
+    /// 別のキャンバスを (x, y) に合成せずそのまま書き込む
+    pub fn copy_from(&mut self, src: &Canvas, x: u32, y: u32) {
+        let width = src.width.min(self.width.saturating_sub(x)) as usize * 4;
+        for row in 0..src.height.min(self.height.saturating_sub(y)) as usize {
+            let from = row * src.width as usize * 4;
+            let to = ((y as usize + row) * self.width as usize + x as usize) * 4;
+            self.pixels[to..to + width].copy_from_slice(&src.pixels[from..from + width]);
+        }
+    }
+
+    /// 1ピクセルに色を重ねる（source-over合成、範囲外は無視）
+    fn blend(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let src_a = color.a.clamp(0.0, 1.0);
+        if src_a <= 0.0 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &mut self.pixels[i..i + 4];
+        let dst_a = pixel[3] as f64 / 255.0;
+        let out_a = src_a + dst_a * (1.0 - src_a);
+        for (dst, src) in pixel.iter_mut().zip([color.r, color.g, color.b]) {
+            let value = (src as f64 * src_a + *dst as f64 * dst_a * (1.0 - src_a)) / out_a;
+            *dst = value.round() as u8;
+        }
+        pixel[3] = (out_a * 255.0).round() as u8;
+    }
+
+    /// 矩形を塗りつぶす（キャンバス外にはみ出した部分は切り取る）
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        let (x0, y0) = (x.max(0), y.max(0));
+        let x1 = x.saturating_add(width).min(self.width as i32);
+        let y1 = y.saturating_add(height).min(self.height as i32);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.blend(px, py, color);
+            }
+        }
+    }
+
+    /// 幅1の縁取り付きの円を描く（縁はピクセル中心からの距離でアンチエイリアスする）
+    /// x が right 以上の部分は描かない
+    pub fn fill_circle(&mut self, cx: f64, cy: f64, radius: f64, (fill, stroke): (Color, Color), right: i32) {
+        let reach = radius + 1.0;
+        let (x0, x1) = ((cx - reach).floor() as i32, ((cx + reach).ceil() as i32).min(right - 1));
+        let (y0, y1) = ((cy - reach).floor() as i32, (cy + reach).ceil() as i32);
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let distance = ((px as f64 + 0.5 - cx).powi(2) + (py as f64 + 0.5 - cy).powi(2)).sqrt();
+                let inside = (radius - distance + 0.5).clamp(0.0, 1.0);
+                if inside > 0.0 {
+                    self.blend(px, py, fill.with_alpha(fill.a * inside));
+                }
+                let edge = (1.0 - (distance - radius).abs()).clamp(0.0, 1.0);
+                if edge > 0.0 {
+                    self.blend(px, py, stroke.with_alpha(stroke.a * edge));
+                }
+            }
+        }
+    }
+
+    /// (x, y) のRGBA
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    /// PNGにエンコードする
+    pub fn encode_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // 更新のたびに送るので圧縮率より速度を優先する
+        encoder.set_compression(png::Compression::Fast);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(bytes)
+    }
+}
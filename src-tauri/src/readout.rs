@@ -0,0 +1,110 @@
+// ヒートマップ上の1点の板の状態（ツールチップ・十字線の表示用）
+use crate::book::OrderBookState;
+use crate::feed::Aggregation;
+use serde::{Deserialize, Serialize};
+
+/// 問い合わせる位置
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ReadoutPoint {
+    // ヒートマップのviewBox上の座標
+    Pixel { x: f64, y: f64 },
+    // 時刻（ミリ秒）と価格
+    Point { time: i64, price: f64 },
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BookReadout {
+    pub aggregation: Aggregation,
+    // 指した時刻の板を表すスナップショットの時刻（集計した区間では区間の開始時刻）
+    pub time: i64,
+    // 指した価格に最も近い価格レベル（近くにレベルがなければ指した価格）
+    pub price: f64,
+    // その価格レベルの数量と注文数（板がなければ 0）
+    pub bid_size: f64,
+    pub bid_orders: i32,
+    pub ask_size: f64,
+    pub ask_orders: i32,
+    // その時点の最良気配の中間（板が片側しかなければ null）
+    pub mid: Option<f64>,
+}
+
+impl BookReadout {
+    /// time の時点の板で、price から tolerance 以内にある最も近い価格レベルを読む
+    /// time が履歴より前なら None
+    pub fn at(aggregation: Aggregation, state: &OrderBookState, (time, price): (i64, f64), tolerance: f64) -> Option<Self> {
+        let history = state.history();
+        let index = history.partition_point(|(ts, _, _)| *ts <= time).checked_sub(1)?;
+        let (ts, buy, sell) = &history[index];
+
+        let nearest = buy.keys().chain(sell.keys())
+            .map(|p| p.into_inner())
+            .filter(|p| (p - price).abs() <= tolerance)
+            .min_by(|a, b| (a - price).abs().total_cmp(&(b - price).abs()));
+        let level = nearest.unwrap_or(price);
+        let (bid, ask) = (buy.get(level), sell.get(level));
+
+        let best_bid = buy.keys().next_back().map(|p| p.into_inner());
+        let best_ask = sell.keys().next().map(|p| p.into_inner());
+        Some(Self {
+            aggregation,
+            time: *ts,
+            price: level,
+            bid_size: bid.map_or(0.0, |l| l.size),
+            bid_orders: bid.map_or(0, |l| l.orders),
+            ask_size: ask.map_or(0.0, |l| l.size),
+            ask_orders: ask.map_or(0, |l| l.orders),
+            mid: best_bid.zip(best_ask).map(|(bid, ask)| (bid + ask) / 2.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Level;
+    use crate::history::BucketStat;
+    use ordered_float::OrderedFloat;
+
+    fn state() -> OrderBookState {
+        let mut state = OrderBookState::with_history(30_000, BucketStat::default(), usize::MAX);
+        for (ts, bid) in [(1_000, 99.0), (2_000, 100.0)] {
+            state.buy.clear();
+            state.buy.insert(OrderedFloat(bid), Level { size: 2.0, orders: 1 });
+            state.buy.insert(OrderedFloat(bid - 1.0), Level { size: 5.0, orders: 4 });
+            state.sell.clear();
+            state.sell.insert(OrderedFloat(101.0), Level { size: 3.0, orders: 2 });
+            state.update_history(ts);
+        }
+        state
+    }
+
+    #[test]
+    fn reads_nearest_level_of_snapshot() {
+        let state = state();
+        let readout = BookReadout::at(Aggregation::default(), &state, (1_500, 98.2), 0.5).unwrap();
+        assert_eq!(readout.time, 1_000);
+        assert_eq!(readout.price, 98.0);
+        assert_eq!((readout.bid_size, readout.bid_orders), (5.0, 4));
+        assert_eq!((readout.ask_size, readout.ask_orders), (0.0, 0));
+        assert_eq!(readout.mid, Some(100.0));
+
+        // 後のスナップショットではレベルが動いている
+        let readout = BookReadout::at(Aggregation::default(), &state, (2_500, 100.9), 0.5).unwrap();
+        assert_eq!(readout.time, 2_000);
+        assert_eq!(readout.price, 101.0);
+        assert_eq!((readout.ask_size, readout.ask_orders), (3.0, 2));
+        assert_eq!(readout.mid, Some(100.5));
+    }
+
+    #[test]
+    fn empty_price_and_time_before_history() {
+        let state = state();
+        // 近くにレベルがなければ指した価格のまま数量 0
+        let readout = BookReadout::at(Aggregation::default(), &state, (2_000, 120.0), 0.5).unwrap();
+        assert_eq!(readout.price, 120.0);
+        assert_eq!(readout.bid_size + readout.ask_size, 0.0);
+        assert!(BookReadout::at(Aggregation::default(), &state, (500, 100.0), 0.5).is_none());
+    }
+}
@@ -0,0 +1,30 @@
+// テストで使う板・約定の組み立て
+use crate::book::{BookSide, Level, OrderBookState};
+use crate::feed::{Side, WsTrade};
+use ordered_float::OrderedFloat;
+
+/// (価格, 数量, 注文数) の並びから板の片側を作る
+pub fn side(levels: &[(f64, f64, i32)]) -> BookSide {
+    levels.iter().map(|&(price, size, orders)| (OrderedFloat(price), Level { size, orders })).collect()
+}
+
+/// 両側の板を持つ状態を作る（履歴には入れない）
+pub fn book(buy: &[(f64, f64, i32)], sell: &[(f64, f64, i32)]) -> OrderBookState {
+    let mut state = OrderBookState::new();
+    state.buy = side(buy);
+    state.sell = side(sell);
+    state
+}
+
+/// BTCの約定
+pub fn trade(tid: u64, time: i64, side: Side, price: f64, size: f64) -> WsTrade {
+    WsTrade {
+        coin: "BTC".to_string(),
+        side,
+        px: price.to_string(),
+        sz: size.to_string(),
+        time,
+        tid,
+        users: None,
+    }
+}
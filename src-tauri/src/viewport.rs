@@ -0,0 +1,274 @@
+// 表示する価格範囲・時間範囲の決め方
+use serde::{Deserialize, Serialize};
+
+const MAX_TICKS: u32 = 1_000_000;
+const MAX_PERCENT: f64 = 50.0;
+// Midが中心から上下の幅のこの割合より離れたら、中心を寄せ始める
+const RECENTER_EDGE: f64 = 0.75;
+// 中心からこの割合以内まで戻ったら寄せるのをやめる
+const RECENTER_SETTLE: f64 = 0.05;
+// 1フレームで残りの距離のこの割合だけ中心を寄せる
+const RECENTER_RATE: f64 = 0.25;
+// 拡大表示できる最小の時間幅（ミリ秒）
+const MIN_TIME_SPAN: i64 = 5_000;
+// 最新に追従しているとき、右端から時間幅のこの割合以内を指して拡大・縮小したら追従を続ける
+const LIVE_EDGE: f64 = 0.1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum PriceViewport {
+    // 履歴に現れた価格全体
+    #[default]
+    Auto,
+    // Midを中心に上下 ticks 刻みずつ
+    MidTicks { ticks: u32 },
+    // Midを中心に上下 percent % ずつ
+    MidPercent { percent: f64 },
+    // 指定した範囲に固定
+    Locked { min: f64, max: f64 },
+}
+
+impl PriceViewport {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::MidTicks { ticks } if ticks == 0 || ticks > MAX_TICKS => {
+                Err(format!("ticks must be in 1..={}: {}", MAX_TICKS, ticks))
+            }
+            Self::MidPercent { percent } if !(percent > 0.0 && percent <= MAX_PERCENT) => {
+                Err(format!("percent must be in (0, {}]: {}", MAX_PERCENT, percent))
+            }
+            Self::Locked { min, max } if !(min.is_finite() && max.is_finite() && min < max) => {
+                Err(format!("invalid price range: {} to {}", min, max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Midを中心にする場合の上下の幅（tick はMid付近の価格の刻み）
+    pub fn half_width(&self, mid: f64, tick: Option<f64>) -> Option<f64> {
+        match *self {
+            Self::MidTicks { ticks } => tick.map(|tick| tick * ticks as f64),
+            Self::MidPercent { percent } => Some(mid.abs() * percent / 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// Midに追従する表示範囲の中心
+/// 毎フレームMidに合わせると目盛りが揺れ続けるので、Midが端に近づいたときだけ数フレームかけて寄せる
+#[derive(Debug, Clone, Default)]
+pub struct Recenter {
+    center: Option<f64>,
+    moving: bool,
+}
+
+impl Recenter {
+    /// 中心から上下 half の範囲
+    pub fn range(&mut self, mid: f64, half: f64) -> (f64, f64) {
+        let center = match self.center {
+            Some(center) if (mid - center).abs() <= half => {
+                let distance = (mid - center).abs() / half;
+                if distance > RECENTER_EDGE {
+                    self.moving = true;
+                } else if distance < RECENTER_SETTLE {
+                    self.moving = false;
+                }
+                if self.moving {
+                    center + (mid - center) * RECENTER_RATE
+                } else {
+                    center
+                }
+            }
+            // 初回と、Midが範囲の外へ飛んだとき（幅を変えたときを含む）はその場で合わせる
+            _ => {
+                self.moving = false;
+                mid
+            }
+        };
+        self.center = Some(center);
+        (center - half, center + half)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// 表示する時間範囲と価格範囲
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewRange {
+    pub since: i64,
+    pub until: i64,
+    pub min_price: f64,
+    pub max_price: f64,
+}
+
+impl ViewRange {
+    pub fn times(&self) -> (i64, i64) {
+        (self.since, self.until)
+    }
+
+    pub fn prices(&self) -> (f64, f64) {
+        (self.min_price, self.max_price)
+    }
+}
+
+/// 表示する時間範囲
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeViewport {
+    // 表示する時間幅（None は保持期間全体）
+    pub span: Option<i64>,
+    // 右端の時刻（None は最新に追従）
+    pub until: Option<i64>,
+}
+
+impl TimeViewport {
+    /// 最新の時刻 latest と保持期間 window から (since, until) を求める（保持期間の外にははみ出さない）
+    pub fn range(&self, latest: i64, window: i64) -> (i64, i64) {
+        let span = self.span.unwrap_or(window).clamp(MIN_TIME_SPAN.min(window), window);
+        let until = self.until.unwrap_or(latest).clamp(latest - window + span, latest);
+        (until - span, until)
+    }
+
+    /// 表示中の範囲を、time の位置を保ったまま factor 倍の時間幅にする
+    /// 最新に追従しているときは、右端付近（LIVE_EDGE）を指したときだけ右端を最新に保ち、
+    /// それより過去を指したときは右端を固定してその位置を保つ
+    pub fn zoom(&mut self, (since, until): (i64, i64), time: i64, factor: f64, (latest, window): (i64, i64)) {
+        let span = ((until - since) as f64 * factor).round() as i64;
+        let span = span.clamp(MIN_TIME_SPAN.min(window), window);
+        let live_edge = until - ((until - since) as f64 * LIVE_EDGE).round() as i64;
+        if self.until.is_some() || time < live_edge {
+            let time = time.clamp(since, until);
+            let right = (until - time) as f64 * span as f64 / (until - since) as f64;
+            self.set_until(time + right.round() as i64, span, (latest, window));
+        }
+        self.span = (span < window).then_some(span);
+    }
+
+    /// 表示中の範囲を delta ミリ秒ずらす（正なら新しい側）
+    pub fn pan(&mut self, (since, until): (i64, i64), delta: i64, (latest, window): (i64, i64)) {
+        self.set_until(until + delta, until - since, (latest, window));
+    }
+
+    /// 右端を固定する（最新に届いたら追従に戻す）
+    fn set_until(&mut self, until: i64, span: i64, (latest, window): (i64, i64)) {
+        let until = until.max(latest - window + span);
+        self.until = (until < latest).then_some(until);
+    }
+}
+
+/// 拡大・移動する軸
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ViewAxes {
+    #[default]
+    Both,
+    Time,
+    Price,
+}
+
+impl ViewAxes {
+    pub fn time(&self) -> bool {
+        matches!(self, Self::Both | Self::Time)
+    }
+
+    pub fn price(&self) -> bool {
+        matches!(self, Self::Both | Self::Price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_width_by_ticks_or_percent() {
+        assert_eq!(PriceViewport::MidTicks { ticks: 50 }.half_width(100.0, Some(0.01)), Some(0.5));
+        assert_eq!(PriceViewport::MidTicks { ticks: 50 }.half_width(100.0, None), None);
+        assert_eq!(PriceViewport::MidPercent { percent: 2.0 }.half_width(50.0, None), Some(1.0));
+        assert_eq!(PriceViewport::Auto.half_width(50.0, Some(0.01)), None);
+    }
+
+    #[test]
+    fn rejects_invalid_viewports() {
+        assert!(PriceViewport::MidTicks { ticks: 0 }.validate().is_err());
+        assert!(PriceViewport::MidPercent { percent: 0.0 }.validate().is_err());
+        assert!(PriceViewport::MidPercent { percent: 80.0 }.validate().is_err());
+        assert!(PriceViewport::Locked { min: 2.0, max: 1.0 }.validate().is_err());
+        assert!(PriceViewport::Locked { min: 1.0, max: f64::INFINITY }.validate().is_err());
+        assert!(PriceViewport::Locked { min: 1.0, max: 2.0 }.validate().is_ok());
+    }
+
+    #[test]
+    fn recenters_only_near_edges() {
+        let mut recenter = Recenter::default();
+        assert_eq!(recenter.range(100.0, 10.0), (90.0, 110.0));
+        // 中央付近の動きでは範囲を動かさない
+        assert_eq!(recenter.range(105.0, 10.0), (90.0, 110.0));
+
+        // 端に近づくと、数フレームかけてMidへ寄せる
+        let (low, _) = recenter.range(108.0, 10.0);
+        assert!((low - 92.0).abs() < 1e-9);
+        let mut center = 102.0;
+        for _ in 0..30 {
+            let (low, high) = recenter.range(108.0, 10.0);
+            let next = (low + high) / 2.0;
+            assert!(next >= center && next <= 108.0);
+            center = next;
+        }
+        assert!((center - 108.0).abs() < 10.0 * RECENTER_SETTLE);
+
+        // 範囲の外へ飛んだらその場で合わせる
+        assert_eq!(recenter.range(200.0, 10.0), (190.0, 210.0));
+    }
+
+    #[test]
+    fn time_zoom_keeps_anchor() {
+        // 保持期間 60s、最新 100s
+        let limits = (100_000, 60_000);
+        let mut view = TimeViewport::default();
+        assert_eq!(view.range(100_000, 60_000), (40_000, 100_000));
+
+        // 追従中に右端付近を指したときは、右端を最新に保ったまま幅だけ変える
+        view.zoom((40_000, 100_000), 98_000, 0.5, limits);
+        assert_eq!(view, TimeViewport { span: Some(30_000), until: None });
+        assert_eq!(view.range(100_000, 60_000), (70_000, 100_000));
+        // 保持期間より広くはならない
+        view.zoom((70_000, 100_000), 80_000, 4.0, limits);
+        assert_eq!(view, TimeViewport::default());
+
+        // 追従中でも過去を指したときは右端を固定して、指した時刻の位置を保つ
+        view.zoom((40_000, 100_000), 50_000, 0.5, limits);
+        assert_eq!(view, TimeViewport { span: Some(30_000), until: Some(75_000) });
+        assert_eq!(view.range(100_000, 60_000), (45_000, 75_000));
+        // 縮小して右端が最新に届いたら追従に戻る
+        view.zoom((45_000, 75_000), 74_000, 2.0, limits);
+        assert_eq!(view, TimeViewport { span: None, until: None });
+
+        // 右端を固定しているときは指した時刻の位置を保つ
+        let mut view = TimeViewport { span: Some(20_000), until: Some(80_000) };
+        view.zoom((60_000, 80_000), 70_000, 0.5, limits);
+        assert_eq!(view.range(100_000, 60_000), (65_000, 75_000));
+        // 最小の幅より細かくはしない
+        view.zoom((65_000, 75_000), 70_000, 0.01, limits);
+        assert_eq!(view.range(100_000, 60_000), (67_500, 72_500));
+    }
+
+    #[test]
+    fn time_pan_stops_at_edges() {
+        let limits = (100_000, 60_000);
+        let mut view = TimeViewport { span: Some(20_000), until: None };
+        view.pan((80_000, 100_000), -15_000, limits);
+        assert_eq!(view.range(100_000, 60_000), (65_000, 85_000));
+        // 保持期間より古い側へは動かない
+        view.pan((65_000, 85_000), -50_000, limits);
+        assert_eq!(view.range(100_000, 60_000), (40_000, 60_000));
+        // 最新に届いたら追従に戻る
+        view.pan((40_000, 60_000), 45_000, limits);
+        assert_eq!(view.until, None);
+        // 固定した範囲も、古い履歴が捨てられたら保持期間内へ寄せる
+        let view = TimeViewport { span: Some(20_000), until: Some(50_000) };
+        assert_eq!(view.range(120_000, 60_000), (60_000, 80_000));
+    }
+}
@@ -0,0 +1,385 @@
+// 板の更新をバイナリで送るための形式
+// フロントエンド側のデコーダ（src/wire.ts）と同じ形式を保つこと。変更する場合は WIRE_VERSION を上げる
+//
+// すべてリトルエンディアン
+//   0  magic        "HB"
+//   2  version      u8
+//   3  kind         u8（0: スナップショット、1: 差分）
+//   4  time         i64（ミリ秒）
+//   12 nSigFigs     u8（0 は集約なし）
+//   13 mantissa     u8（0 は指定なし）
+//   14 coin length  u8
+//   15 coin         UTF-8
+//   .. bid count    u16
+//   .. ask count    u16
+//   .. levels       (price f64, size f64, orders u32) × (bid count + ask count)、Bidが先
+// 差分では size = 0 がその価格レベルの削除を表す
+//
+// アプリ内でデコードするのはフロントエンドだけで、Rust側のデコーダは形式を往復で確かめるテストに使う
+use crate::book::{BookSide, OrderBookState};
+use crate::feed::Aggregation;
+#[cfg(test)]
+use crate::book::Level;
+#[cfg(test)]
+use ordered_float::OrderedFloat;
+#[cfg(test)]
+use std::fmt;
+
+pub const WIRE_VERSION: u8 = 1;
+const MAGIC: [u8; 2] = *b"HB";
+const LEVEL_BYTES: usize = 20;
+// この回数だけ差分を送ったら、取りこぼしがあっても復帰できるようスナップショットを送る
+const SNAPSHOT_INTERVAL: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Snapshot,
+    Delta,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WireLevel {
+    pub price: f64,
+    pub size: f64,
+    pub orders: u32,
+}
+
+/// 1回分の板の更新
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookFrame {
+    pub kind: FrameKind,
+    pub coin: String,
+    pub aggregation: Aggregation,
+    pub time: i64,
+    pub bids: Vec<WireLevel>,
+    pub asks: Vec<WireLevel>,
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    InvalidCoin,
+}
+
+#[cfg(test)]
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "frame is truncated"),
+            Self::BadMagic => write!(f, "frame does not start with the magic bytes"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported wire version: {}", v),
+            Self::UnknownKind(k) => write!(f, "unknown frame kind: {}", k),
+            Self::InvalidCoin => write!(f, "coin is not valid UTF-8"),
+        }
+    }
+}
+
+#[cfg(test)]
+impl std::error::Error for WireError {}
+
+fn to_wire(levels: &BookSide) -> Vec<WireLevel> {
+    levels
+        .iter()
+        .map(|(price, level)| WireLevel {
+            price: price.into_inner(),
+            size: level.size,
+            orders: level.orders.max(0) as u32,
+        })
+        .collect()
+}
+
+/// previous から current への変化（消えた価格レベルは size = 0）
+fn diff(previous: &BookSide, current: &BookSide) -> Vec<WireLevel> {
+    let mut levels: Vec<WireLevel> = current
+        .iter()
+        .filter(|(price, level)| {
+            previous.get(price).is_none_or(|p| p.size != level.size || p.orders != level.orders)
+        })
+        .map(|(price, level)| WireLevel {
+            price: price.into_inner(),
+            size: level.size,
+            orders: level.orders.max(0) as u32,
+        })
+        .collect();
+    levels.extend(previous.keys().filter(|price| !current.contains_key(price)).map(|price| WireLevel {
+        price: price.into_inner(),
+        size: 0.0,
+        orders: 0,
+    }));
+    levels
+}
+
+/// 先頭から順に読み進めるためのカーソル
+#[cfg(test)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+#[cfg(test)]
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], WireError> {
+        let (head, rest) = self.bytes.split_first_chunk::<N>().ok_or(WireError::Truncated)?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.bytes.len() < len {
+            return Err(WireError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, WireError> {
+        Ok(i64::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, WireError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn levels(&mut self, count: u16) -> Result<Vec<WireLevel>, WireError> {
+        (0..count)
+            .map(|_| Ok(WireLevel { price: self.f64()?, size: self.f64()?, orders: self.u32()? }))
+            .collect()
+    }
+}
+
+impl BookFrame {
+    pub fn snapshot(coin: &str, aggregation: Aggregation, time: i64, state: &OrderBookState) -> Self {
+        Self {
+            kind: FrameKind::Snapshot,
+            coin: coin.to_string(),
+            aggregation,
+            time,
+            bids: to_wire(&state.buy),
+            asks: to_wire(&state.sell),
+        }
+    }
+
+    pub fn delta(coin: &str, aggregation: Aggregation, time: i64, previous: (&BookSide, &BookSide), state: &OrderBookState) -> Self {
+        Self {
+            kind: FrameKind::Delta,
+            coin: coin.to_string(),
+            aggregation,
+            time,
+            bids: diff(previous.0, &state.buy),
+            asks: diff(previous.1, &state.sell),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // coin は最長255バイト（文字の途中では切らない）、各側の価格レベルは最大65535件まで
+        let coin_len = self.coin.char_indices()
+            .map(|(i, c)| i + c.len_utf8())
+            .take_while(|end| *end <= u8::MAX as usize)
+            .last()
+            .unwrap_or(0);
+        let coin = &self.coin.as_bytes()[..coin_len];
+        let (bids, asks) = (
+            &self.bids[..self.bids.len().min(u16::MAX as usize)],
+            &self.asks[..self.asks.len().min(u16::MAX as usize)],
+        );
+
+        let mut bytes = Vec::with_capacity(19 + coin.len() + (bids.len() + asks.len()) * LEVEL_BYTES);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(WIRE_VERSION);
+        bytes.push(match self.kind {
+            FrameKind::Snapshot => 0,
+            FrameKind::Delta => 1,
+        });
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.push(self.aggregation.n_sig_figs.unwrap_or(0));
+        bytes.push(self.aggregation.mantissa.unwrap_or(0));
+        bytes.push(coin.len() as u8);
+        bytes.extend_from_slice(coin);
+        bytes.extend_from_slice(&(bids.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(asks.len() as u16).to_le_bytes());
+        for level in bids.iter().chain(asks) {
+            bytes.extend_from_slice(&level.price.to_le_bytes());
+            bytes.extend_from_slice(&level.size.to_le_bytes());
+            bytes.extend_from_slice(&level.orders.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[cfg(test)]
+    pub fn decode(bytes: &[u8]) -> Result<Self, WireError> {
+        let mut reader = Reader { bytes };
+        if reader.take::<2>()? != MAGIC {
+            return Err(WireError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version != WIRE_VERSION {
+            return Err(WireError::UnsupportedVersion(version));
+        }
+        let kind = match reader.u8()? {
+            0 => FrameKind::Snapshot,
+            1 => FrameKind::Delta,
+            k => return Err(WireError::UnknownKind(k)),
+        };
+        let time = reader.i64()?;
+        let aggregation = Aggregation {
+            n_sig_figs: Some(reader.u8()?).filter(|n| *n != 0),
+            mantissa: Some(reader.u8()?).filter(|m| *m != 0),
+        };
+        let coin_len = reader.u8()? as usize;
+        let coin = std::str::from_utf8(reader.bytes(coin_len)?)
+            .map_err(|_| WireError::InvalidCoin)?
+            .to_string();
+        let (bid_count, ask_count) = (reader.u16()?, reader.u16()?);
+        Ok(Self {
+            kind,
+            coin,
+            aggregation,
+            time,
+            bids: reader.levels(bid_count)?,
+            asks: reader.levels(ask_count)?,
+        })
+    }
+
+    /// 受信側の板に反映する（スナップショットは置き換え、差分は上書き・削除）
+    #[cfg(test)]
+    pub fn apply(&self, buy: &mut BookSide, sell: &mut BookSide) {
+        if self.kind == FrameKind::Snapshot {
+            buy.clear();
+            sell.clear();
+        }
+        for (side, levels) in [(buy, &self.bids), (sell, &self.asks)] {
+            for level in levels {
+                if level.size == 0.0 {
+                    side.remove(&OrderedFloat(level.price));
+                } else {
+                    side.insert(OrderedFloat(level.price), Level { size: level.size, orders: level.orders as i32 });
+                }
+            }
+        }
+    }
+}
+
+/// 1つの送信先に送った板を覚えておき、次にスナップショットと差分のどちらを送るか決める
+#[derive(Default)]
+pub struct BookEncoder {
+    last: Option<(Aggregation, BookSide, BookSide)>,
+    deltas: u32,
+}
+
+impl BookEncoder {
+    pub fn next_frame(&mut self, coin: &str, aggregation: Aggregation, time: i64, state: &OrderBookState) -> BookFrame {
+        let frame = match &self.last {
+            // 集約が変わると価格が揃わないので、スナップショットから送り直す
+            Some((last_aggregation, buy, sell)) if *last_aggregation == aggregation && self.deltas < SNAPSHOT_INTERVAL => {
+                self.deltas += 1;
+                BookFrame::delta(coin, aggregation, time, (buy, sell), state)
+            }
+            _ => {
+                self.deltas = 0;
+                BookFrame::snapshot(coin, aggregation, time, state)
+            }
+        };
+        self.last = Some((aggregation, state.buy.clone(), state.sell.clone()));
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::book;
+
+    fn assert_same_book(a: &BookSide, b: &BookSide) {
+        let flatten = |side: &BookSide| -> Vec<(f64, f64, i32)> {
+            side.iter().map(|(p, l)| (p.into_inner(), l.size, l.orders)).collect()
+        };
+        assert_eq!(flatten(a), flatten(b));
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let state = book(&[(99.5, 1.25, 3), (99.0, 10.0, 1)], &[(100.5, 0.001, 2)]);
+        let frame = BookFrame::snapshot("@107", Aggregation { n_sig_figs: Some(5), mantissa: Some(2) }, 1_700_000_000_123, &state);
+        let bytes = frame.encode();
+        assert_eq!(&bytes[..4], &[b'H', b'B', WIRE_VERSION, 0]);
+        assert_eq!(bytes.len(), 15 + 4 + 4 + 3 * LEVEL_BYTES);
+        assert_eq!(BookFrame::decode(&bytes), Ok(frame));
+    }
+
+    #[test]
+    fn full_precision_aggregation_round_trip() {
+        let state = book(&[(1.0, 1.0, 1)], &[]);
+        let frame = BookFrame::snapshot("BTC", Aggregation { n_sig_figs: None, mantissa: None }, 0, &state);
+        assert_eq!(BookFrame::decode(&frame.encode()).unwrap().aggregation, frame.aggregation);
+    }
+
+    #[test]
+    fn deltas_reproduce_book() {
+        let mut encoder = BookEncoder::default();
+        let (mut buy, mut sell) = (BookSide::new(), BookSide::new());
+        let updates = [
+            book(&[(99.0, 1.0, 1), (98.0, 2.0, 2)], &[(101.0, 1.0, 1)]),
+            // 数量の変化・価格レベルの追加と削除
+            book(&[(99.0, 1.5, 2), (97.0, 3.0, 1)], &[(101.0, 1.0, 1), (102.0, 4.0, 5)]),
+            book(&[], &[(100.0, 0.5, 1)]),
+        ];
+        for (i, update) in updates.iter().enumerate() {
+            let frame = encoder.next_frame("ETH", Aggregation::default(), i as i64, update);
+            assert_eq!(frame.kind, if i == 0 { FrameKind::Snapshot } else { FrameKind::Delta });
+            let decoded = BookFrame::decode(&frame.encode()).unwrap();
+            decoded.apply(&mut buy, &mut sell);
+            assert_same_book(&buy, &update.buy);
+            assert_same_book(&sell, &update.sell);
+        }
+    }
+
+    #[test]
+    fn aggregation_change_sends_snapshot() {
+        let mut encoder = BookEncoder::default();
+        let update = book(&[(99.0, 1.0, 1)], &[(101.0, 1.0, 1)]);
+        encoder.next_frame("ETH", Aggregation::sig_figs(5), 0, &update);
+        let frame = encoder.next_frame("ETH", Aggregation::sig_figs(4), 1, &update);
+        assert_eq!(frame.kind, FrameKind::Snapshot);
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        let bytes = BookFrame::snapshot("ETH", Aggregation::default(), 0, &book(&[(99.0, 1.0, 1)], &[])).encode();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[2] = WIRE_VERSION + 1;
+        assert_eq!(BookFrame::decode(&wrong_version), Err(WireError::UnsupportedVersion(WIRE_VERSION + 1)));
+
+        let mut wrong_kind = bytes.clone();
+        wrong_kind[3] = 7;
+        assert_eq!(BookFrame::decode(&wrong_kind), Err(WireError::UnknownKind(7)));
+
+        assert_eq!(BookFrame::decode(b"XX"), Err(WireError::BadMagic));
+        assert_eq!(BookFrame::decode(&bytes[..bytes.len() - 1]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn long_coin_is_cut_at_char_boundary() {
+        // 3バイトの文字を86個並べると258バイトになり、255バイト目で切ると文字の途中になる
+        let coin = "あ".repeat(86);
+        let frame = BookFrame::snapshot(&coin, Aggregation::default(), 0, &book(&[], &[]));
+        let decoded = BookFrame::decode(&frame.encode()).unwrap();
+        assert_eq!(decoded.coin, "あ".repeat(85));
+    }
+}
@@ -1,20 +1,781 @@
-import { useEffect, useState } from 'react';
+import { FormEvent, MouseEvent, WheelEvent, useEffect, useRef, useState } from 'react';
 import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/core';
+import { streamBook } from './wire';
 import './App.css';
 
-function App() {
-  const [svgContent, setSvgContent] = useState('');
+type Market = {
+  coin: string;
+  symbol: string;
+  kind: 'perp' | 'spot';
+  szDecimals: number;
+  priceDecimals: number;
+  tickSize: number;
+};
+
+type Aggregation = {
+  nSigFigs: number | null;
+  mantissa: number | null;
+};
+
+type AggregationMode = { mode: 'auto' } | ({ mode: 'fixed' } & Aggregation);
+
+type HeatmapMetric = 'size' | 'averageOrderSize';
+
+type HeatmapOutput = 'image' | 'grid';
+
+// 古い履歴を集計した区間の代表値
+type BucketStat = 'max' | 'mean' | 'last';
+
+type ScaleCurve = 'linear' | 'sqrt' | 'log';
+
+type IntensityScale = {
+  curve: ScaleCurve;
+  // 基準にするパーセンタイル（null は最大値）
+  clipPercentile: number | null;
+};
+
+type ColorStop = {
+  position: number;
+  // '#rrggbb' または '#rrggbbaa'
+  color: string;
+};
+
+type Palette =
+  | { name: 'classic' | 'viridis' | 'inferno' | 'bookmap' }
+  | { name: 'custom'; stops: ColorStop[] };
+
+type ColorMap = {
+  palette: Palette;
+  // Bid/Askを区別せずに同じグラデーションで塗る
+  combined: boolean;
+};
+
+type TimeAxis = {
+  zone: 'local' | 'utc';
+  // 目盛りの位置に縦の補助線を引く
+  grid: boolean;
+};
+
+// 表示する価格範囲の決め方
+type PriceViewport =
+  | { mode: 'auto' }
+  | { mode: 'midTicks'; ticks: number }
+  | { mode: 'midPercent'; percent: number }
+  | { mode: 'locked'; min: number; max: number };
+
+// 保持期間のうち表示する時間範囲
+type TimeViewport = {
+  // 表示する時間幅（null は保持期間全体）
+  span: number | null;
+  // 右端の時刻（null は最新に追従）
+  until: number | null;
+};
+
+// 拡大・移動する軸
+type ViewAxes = 'both' | 'time' | 'price';
+
+const PALETTES = ['classic', 'viridis', 'inferno', 'bookmap', 'custom'] as const;
+
+// '#000000, #0000ff, #ffff00' のような色の並びを等間隔のグラデーションにする
+const parseStops = (text: string): ColorStop[] => {
+  const colors = text.split(',').map(c => c.trim()).filter(c => c.length > 0);
+  return colors.map((color, i) => ({ position: i / Math.max(1, colors.length - 1), color }));
+};
+
+// 基準値の選択肢（ラベル, パーセンタイル）
+const CLIP_PERCENTILES: [string, number | null][] = [
+  ['Max', null],
+  ['P99', 99],
+  ['P95', 95],
+];
+
+type CoinInfo = {
+  coin: string;
+  event: string;
+  gridEvent: string;
+  mode: AggregationMode;
+  metric: HeatmapMetric;
+  scale: IntensityScale;
+  colors: ColorMap;
+  timeAxis: TimeAxis;
+  viewport: PriceViewport;
+  timeViewport: TimeViewport;
+  // 表示する時間幅（ミリ秒）
+  windowMs: number;
+  stat: BucketStat;
+  // 履歴に使うメモリの上限（MB）と現在の使用量の概算（バイト）
+  historyLimitMb: number;
+  historyBytes: number;
+  output: HeatmapOutput;
+};
+
+type HeatmapData = {
+  coin: string;
+  aggregation: Aggregation;
+  since: number;
+  until: number;
+  minPrice: number;
+  maxPrice: number;
+  // ヒートマップ本体のPNG（data URL）と、同じviewBoxで重ねる補助表示のSVG
+  image: string;
+  overlay: string;
+};
+
+// ヒートマップ上の1点の板の状態
+type BookReadout = {
+  aggregation: Aggregation;
+  // 板を表すスナップショットの時刻
+  time: number;
+  // 最も近い価格レベル（近くになければ指した価格）
+  price: number;
+  bidSize: number;
+  bidOrders: number;
+  askSize: number;
+  askOrders: number;
+  mid: number | null;
+};
+
+type FrameStats = {
+  fps: number;
+  rendered: number;
+  dropped: number;
+  coalesced: number;
+  lastRenderMs: number;
+  maxRenderMs: number;
+};
+
+type HeatmapGrid = {
+  coin: string;
+  aggregation: Aggregation;
+  metric: HeatmapMetric;
+  // 表示する時間範囲。列 i は times[i] から次の列の時刻まで（最後の列は until まで）の板
+  since: number;
+  until: number;
+  times: number[];
+  // 行 r の価格は minPrice + r * priceStep
+  minPrice: number;
+  priceStep: number;
+  rows: number;
+  // 列ごとに rows 個並んだ強度（-1〜1 の f32 リトルエンディアン）のbase64。正はBid、負はAsk
+  values: string;
+  scale: IntensityScale;
+  reference: number | null;
+  colors: ColorMap;
+  // 強度を256段階に分けた色（RGBA各8bitのbase64）。正の値と負の値で使い分ける
+  positiveRamp: string;
+  negativeRamp: string;
+  bestBid: (number | null)[];
+  bestAsk: (number | null)[];
+  mid: (number | null)[];
+};
+
+// nSigFigs 2〜5（null は集約なし）、mantissa は nSigFigs = 5 のときのみ
+const AGGREGATIONS: [string, AggregationMode][] = [
+  ['Auto', { mode: 'auto' }],
+  ['Full', { mode: 'fixed', nSigFigs: null, mantissa: null }],
+  ['5', { mode: 'fixed', nSigFigs: 5, mantissa: null }],
+  ['5 ×2', { mode: 'fixed', nSigFigs: 5, mantissa: 2 }],
+  ['5 ×5', { mode: 'fixed', nSigFigs: 5, mantissa: 5 }],
+  ['4', { mode: 'fixed', nSigFigs: 4, mantissa: null }],
+  ['3', { mode: 'fixed', nSigFigs: 3, mantissa: null }],
+  ['2', { mode: 'fixed', nSigFigs: 2, mantissa: null }],
+];
 
+const modeLabel = (a: AggregationMode) =>
+  AGGREGATIONS.find(([, b]) => a.mode === 'auto'
+    ? b.mode === 'auto'
+    : b.mode === 'fixed' && b.nSigFigs === a.nSigFigs && b.mantissa === a.mantissa)?.[0] ?? '5';
+
+// 表示する時間幅の選択肢（ラベル, ミリ秒）
+const TIME_WINDOWS: [string, number][] = [
+  ['30s', 30_000],
+  ['1m', 60_000],
+  ['5m', 300_000],
+  ['15m', 900_000],
+  ['1h', 3_600_000],
+  ['4h', 14_400_000],
+];
+
+// 履歴のメモリ上限の選択肢（MB）
+const HISTORY_LIMITS = [16, 64, 256];
+
+// 描画のフレームレートの選択肢（1〜30fps）
+const FRAME_RATES = [1, 5, 10, 15, 20, 30];
+
+// ホイール1段あたりの表示範囲の拡大率
+const ZOOM_STEP = 1.25;
+
+// ヒートマップのviewBoxと、その左上にある本体（時間×価格の領域）の大きさ
+const VIEW_BOX = { width: 1920, height: 1080 };
+const HEATMAP_BODY = { width: 1360, height: 896 };
+
+// 時刻ラベル（HH:MM:SS.mmm）
+const formatTime = (time: number, zone: TimeAxis['zone']) => {
+  const date = new Date(time);
+  const [h, m, s, ms] = zone === 'utc'
+    ? [date.getUTCHours(), date.getUTCMinutes(), date.getUTCSeconds(), date.getUTCMilliseconds()]
+    : [date.getHours(), date.getMinutes(), date.getSeconds(), date.getMilliseconds()];
+  return `${[h, m, s].map(v => String(v).padStart(2, '0')).join(':')}.${String(ms).padStart(3, '0')}`;
+};
+
+const decodeBytes = (base64: string) => Uint8Array.from(atob(base64), c => c.charCodeAt(0));
+
+const decodeValues = (base64: string) => new Float32Array(decodeBytes(base64).buffer);
+
+// 数値グリッドを時間に比例した幅（本体と同じ列数）で描き、CSSで引き伸ばす
+// スナップショットの間隔は一定ではない（古い側は集計した区間）ので、各列は次の列の時刻まで伸ばす
+function GridCanvas({ grid }: { grid: HeatmapGrid }) {
+  const ref = useRef<HTMLCanvasElement>(null);
+
+  useEffect(() => {
+    const canvas = ref.current;
+    const ctx = canvas?.getContext('2d');
+    const columns = grid.times.length;
+    if (!canvas || !ctx || columns === 0 || grid.reference === null || grid.until <= grid.since) {
+      return;
+    }
+    const width = HEATMAP_BODY.width;
+    canvas.width = width;
+    canvas.height = grid.rows;
+
+    const values = decodeValues(grid.values);
+    const positive = decodeBytes(grid.positiveRamp);
+    const negative = decodeBytes(grid.negativeRamp);
+    const image = ctx.createImageData(width, grid.rows);
+    const rowOf = (price: number) => grid.rows - 1 - Math.round((price - grid.minPrice) / grid.priceStep);
+    // 時刻を列に変換する（表示範囲の手前から続く列は左端から描く）
+    const xOf = (time: number) =>
+      Math.min(width - 1, Math.max(0, Math.floor((time - grid.since) / (grid.until - grid.since) * width)));
+    for (let c = 0; c < columns; c++) {
+      const x0 = xOf(grid.times[c]);
+      const x1 = c + 1 < columns ? xOf(grid.times[c + 1]) : x0 + 1;
+      if (x1 <= x0) {
+        continue;
+      }
+      for (let r = 0; r < grid.rows; r++) {
+        const value = values[c * grid.rows + r];
+        if (value === 0) {
+          continue;
+        }
+        const step = Math.round(Math.min(1, Math.abs(value)) * 255) * 4;
+        const color = (value > 0 ? positive : negative).subarray(step, step + 4);
+        const row = (grid.rows - 1 - r) * width;
+        for (let x = x0; x < x1; x++) {
+          image.data.set(color, (row + x) * 4);
+        }
+      }
+      const mid = grid.mid[c];
+      const y = mid === null ? -1 : rowOf(mid);
+      if (y >= 0 && y < grid.rows) {
+        image.data.fill(255, (y * width + x0) * 4, (y * width + x1) * 4);
+      }
+    }
+    ctx.putImageData(image, 0, 0);
+  }, [grid]);
+
+  return (
+    <canvas ref={ref} style={{
+      position: 'absolute',
+      inset: 0,
+      width: '100%',
+      height: '100%',
+      backgroundColor: '#000',
+      imageRendering: 'pixelated'
+    }} />
+  );
+}
+
+function Heatmap({ coin, eventName, gridEventName, title, mode, metric, scale, colors, timeAxis, viewport, timeViewport, windowMs, stat, historyLimitMb, historyBytes, output, onModeChange, onMetricChange, onScaleChange, onColorsChange, onTimeAxisChange, onViewportChange, onViewChange, onWindowChange, onStatChange, onHistoryLimitChange, onOutputChange, onRemove }: {
+  coin: string;
+  eventName: string;
+  gridEventName: string;
+  title: string;
+  mode: AggregationMode;
+  metric: HeatmapMetric;
+  scale: IntensityScale;
+  colors: ColorMap;
+  timeAxis: TimeAxis;
+  viewport: PriceViewport;
+  timeViewport: TimeViewport;
+  windowMs: number;
+  stat: BucketStat;
+  historyLimitMb: number;
+  historyBytes: number;
+  output: HeatmapOutput;
+  onModeChange: (mode: AggregationMode) => void;
+  onMetricChange: (metric: HeatmapMetric) => void;
+  onScaleChange: (scale: IntensityScale) => void;
+  onColorsChange: (colors: ColorMap) => void;
+  onTimeAxisChange: (timeAxis: TimeAxis) => void;
+  onViewportChange: (viewport: PriceViewport) => void;
+  // 拡大・移動で表示範囲が変わった
+  onViewChange: () => void;
+  onWindowChange: (windowMs: number) => void;
+  onStatChange: (stat: BucketStat) => void;
+  onHistoryLimitChange: (limitMb: number) => void;
+  onOutputChange: (output: HeatmapOutput) => void;
+  onRemove: () => void;
+}) {
+  const [data, setData] = useState<HeatmapData | null>(null);
+  const [grid, setGrid] = useState<HeatmapGrid | null>(null);
+  const [quote, setQuote] = useState<{ bid?: number; ask?: number } | null>(null);
+  // ドラッグ中の直前のポインタ位置（viewBox の座標）
+  const dragging = useRef<{ x: number; y: number } | null>(null);
+  // ポインタの位置（コンテナ内のピクセル）と、そこの板の状態
+  const [hover, setHover] = useState<{ left: number; top: number; readout: BookReadout | null } | null>(null);
+  const label = modeLabel(mode);
+
+  // 最良気配は描画を待たずにバイナリの板から表示する
   useEffect(() => {
-    const unlisten = listen('orderbook-update', (event: any) => {
-      setSvgContent(event.payload.svg);
+    setQuote(null);
+    return streamBook(coin, (bids, asks) => {
+      setQuote({
+        bid: bids.size ? Math.max(...bids.keys()) : undefined,
+        ask: asks.size ? Math.min(...asks.keys()) : undefined,
+      });
     });
+  }, [coin]);
+
+  useEffect(() => {
+    setData(null);
+    setGrid(null);
+    const unlisten = output === 'grid'
+      ? listen<HeatmapGrid>(gridEventName, event => setGrid(event.payload))
+      : listen<HeatmapData>(eventName, event => setData(event.payload));
 
     return () => {
       unlisten.then(f => f());
     };
+  }, [eventName, gridEventName, output, label]);
+
+  // 表示中の価格範囲
+  const range = data
+    ? [data.minPrice, data.maxPrice]
+    : grid && [grid.minPrice, grid.minPrice + (grid.rows - 1) * grid.priceStep];
+  const aggregation = data?.aggregation ?? grid?.aggregation;
+
+  // ポインタの位置をviewBoxの座標にする
+  // 画像はviewBox全体を縦横比を保って収め、数値グリッドは本体だけを全面に引き伸ばして描く（どちらも時間に比例）
+  const viewBoxPoint = (e: MouseEvent<HTMLDivElement>) => {
+    const rect = e.currentTarget.getBoundingClientRect();
+    const [x, y] = [e.clientX - rect.left, e.clientY - rect.top];
+    if (grid) {
+      return { x: x / rect.width * HEATMAP_BODY.width, y: y / rect.height * HEATMAP_BODY.height };
+    }
+    const scale = Math.min(rect.width / VIEW_BOX.width, rect.height / VIEW_BOX.height);
+    return {
+      x: (x - (rect.width - VIEW_BOX.width * scale) / 2) / scale,
+      y: (y - (rect.height - VIEW_BOX.height * scale) / 2) / scale,
+    };
+  };
+
+  // ポインタの位置を中心に拡大・縮小する（Shiftで時間のみ、Altで価格のみ）
+  // 最新に追従中でも、右端付近より過去を指したときは右端が固定されて一時停止になる
+  const zoom = (e: WheelEvent<HTMLDivElement>) => {
+    const { x, y } = viewBoxPoint(e);
+    const axes: ViewAxes = e.shiftKey ? 'time' : e.altKey ? 'price' : 'both';
+    invoke('zoom_viewport', { coin, x, y, factor: e.deltaY > 0 ? ZOOM_STEP : 1 / ZOOM_STEP, axes })
+      .then(onViewChange)
+      .catch(err => console.error(err));
+  };
+
+  // ドラッグで表示範囲を動かす（操作部品の上では始めない）
+  const startDrag = (e: MouseEvent<HTMLDivElement>) => {
+    if (e.button !== 0 || (e.target as HTMLElement).closest('select, input, button, label')) {
+      return;
+    }
+    dragging.current = viewBoxPoint(e);
+  };
+
+  // ドラッグ中は表示範囲を動かし、それ以外はポインタの位置の板の状態を問い合わせる
+  const move = (e: MouseEvent<HTMLDivElement>) => {
+    const point = viewBoxPoint(e);
+    const last = dragging.current;
+    if (!last) {
+      const rect = e.currentTarget.getBoundingClientRect();
+      const [left, top] = [e.clientX - rect.left, e.clientY - rect.top];
+      invoke<BookReadout | null>('get_book_readout', { coin, point: { kind: 'pixel', ...point } })
+        .then(readout => setHover({ left, top, readout }))
+        .catch(err => console.error(err));
+      return;
+    }
+    setHover(null);
+    dragging.current = point;
+    // 内容がポインタに付いてくるよう、表示範囲は逆向きに動かす
+    invoke('pan_viewport', { coin, dx: last.x - point.x, dy: last.y - point.y, axes: 'both' })
+      .catch(err => console.error(err));
+  };
+
+  const endDrag = () => {
+    if (dragging.current) {
+      dragging.current = null;
+      onViewChange();
+    }
+  };
+
+  const leave = () => {
+    endDrag();
+    setHover(null);
+  };
+
+  // 選んだ決め方の既定値（固定は表示中の範囲）
+  const changeViewportMode = (mode: PriceViewport['mode']) => {
+    switch (mode) {
+      case 'auto':
+        onViewportChange({ mode });
+        break;
+      case 'midTicks':
+        onViewportChange({ mode, ticks: 100 });
+        break;
+      case 'midPercent':
+        onViewportChange({ mode, percent: 1 });
+        break;
+      case 'locked':
+        if (range) {
+          onViewportChange({ mode, min: range[0], max: range[1] });
+        }
+        break;
+    }
+  };
+
+  // 表示中のヒートマップをSVGファイルとして保存する
+  const exportSvg = () => {
+    invoke<string>('export_svg', { coin })
+      .then(svg => {
+        const url = URL.createObjectURL(new Blob([svg], { type: 'image/svg+xml' }));
+        const a = document.createElement('a');
+        a.href = url;
+        a.download = `${title.replace(/[^\w-]/g, '_')}-heatmap.svg`;
+        a.click();
+        URL.revokeObjectURL(url);
+      })
+      .catch(err => console.error(err));
+  };
+
+  const resetZoom = () => {
+    invoke('reset_viewport', { coin })
+      .then(onViewChange)
+      .catch(err => console.error(err));
+  };
+
+  return (
+    <div onWheel={zoom} onMouseDown={startDrag} onMouseMove={move} onMouseUp={endDrag} onMouseLeave={leave} onDoubleClick={resetZoom} style={{
+      position: 'relative',
+      minWidth: 0,
+      minHeight: 0,
+      display: 'flex',
+      justifyContent: 'center',
+      alignItems: 'center'
+    }}>
+      <div style={{
+        position: 'absolute',
+        top: 4,
+        left: 8,
+        zIndex: 1,
+        color: '#fff',
+        display: 'flex',
+        gap: '8px'
+      }}>
+        <span>{title}</span>
+        <select
+          value={label}
+          onChange={e => {
+            const found = AGGREGATIONS.find(([label]) => label === e.target.value);
+            if (found) {
+              onModeChange(found[1]);
+            }
+          }}
+        >
+          {AGGREGATIONS.map(([label]) => (
+            <option key={label} value={label}>{label}</option>
+          ))}
+        </select>
+        <select value={metric} onChange={e => onMetricChange(e.target.value as HeatmapMetric)}>
+          <option value="size">Size</option>
+          <option value="averageOrderSize">Avg order size</option>
+        </select>
+        <select value={scale.curve} onChange={e => onScaleChange({ ...scale, curve: e.target.value as ScaleCurve })}>
+          <option value="linear">Linear</option>
+          <option value="sqrt">Sqrt</option>
+          <option value="log">Log</option>
+        </select>
+        <select
+          value={CLIP_PERCENTILES.find(([, p]) => p === scale.clipPercentile)?.[0]}
+          onChange={e => {
+            const found = CLIP_PERCENTILES.find(([label]) => label === e.target.value);
+            if (found) {
+              onScaleChange({ ...scale, clipPercentile: found[1] });
+            }
+          }}
+        >
+          {CLIP_PERCENTILES.map(([label]) => (
+            <option key={label} value={label}>{label}</option>
+          ))}
+        </select>
+        <select
+          value={colors.palette.name}
+          onChange={e => {
+            const name = e.target.value as Palette['name'];
+            if (name !== 'custom') {
+              onColorsChange({ ...colors, palette: { name } });
+              return;
+            }
+            const current = colors.palette.name === 'custom' ? colors.palette.stops.map(s => s.color).join(', ') : '#000000, #0000ff, #ffff00, #ff0000';
+            const text = window.prompt('Gradient colors (low to high)', current);
+            if (text !== null) {
+              onColorsChange({ ...colors, palette: { name, stops: parseStops(text) } });
+            }
+          }}
+        >
+          {PALETTES.map(name => (
+            <option key={name} value={name}>{name[0].toUpperCase() + name.slice(1)}</option>
+          ))}
+        </select>
+        <label>
+          <input
+            type="checkbox"
+            checked={colors.combined}
+            onChange={e => onColorsChange({ ...colors, combined: e.target.checked })}
+          />
+          Combined
+        </label>
+        <select value={timeAxis.zone} onChange={e => onTimeAxisChange({ ...timeAxis, zone: e.target.value as TimeAxis['zone'] })}>
+          <option value="local">Local</option>
+          <option value="utc">UTC</option>
+        </select>
+        <label>
+          <input
+            type="checkbox"
+            checked={timeAxis.grid}
+            onChange={e => onTimeAxisChange({ ...timeAxis, grid: e.target.checked })}
+          />
+          Grid
+        </label>
+        <select value={viewport.mode} onChange={e => changeViewportMode(e.target.value as PriceViewport['mode'])}>
+          <option value="auto">Auto range</option>
+          <option value="midTicks">Mid ± ticks</option>
+          <option value="midPercent">Mid ± %</option>
+          <option value="locked">Locked</option>
+        </select>
+        {viewport.mode === 'midTicks' && (
+          <input
+            type="number"
+            min={1}
+            value={viewport.ticks}
+            style={{ width: '5em' }}
+            onChange={e => onViewportChange({ ...viewport, ticks: Math.max(1, Math.round(Number(e.target.value))) })}
+          />
+        )}
+        {viewport.mode === 'midPercent' && (
+          <input
+            type="number"
+            min={0.01}
+            max={50}
+            step={0.1}
+            value={viewport.percent}
+            style={{ width: '5em' }}
+            onChange={e => onViewportChange({ ...viewport, percent: Number(e.target.value) })}
+          />
+        )}
+        <select value={windowMs} onChange={e => onWindowChange(Number(e.target.value))}>
+          {TIME_WINDOWS.map(([label, ms]) => (
+            <option key={ms} value={ms}>{label}</option>
+          ))}
+        </select>
+        <select value={stat} onChange={e => onStatChange(e.target.value as BucketStat)}>
+          <option value="max">Max</option>
+          <option value="mean">Mean</option>
+          <option value="last">Last</option>
+        </select>
+        <select
+          value={historyLimitMb}
+          title={`${(historyBytes / 1024 / 1024).toFixed(1)} MB used`}
+          onChange={e => onHistoryLimitChange(Number(e.target.value))}
+        >
+          {HISTORY_LIMITS.map(mb => (
+            <option key={mb} value={mb}>{mb} MB</option>
+          ))}
+        </select>
+        <select value={output} onChange={e => onOutputChange(e.target.value as HeatmapOutput)}>
+          <option value="image">Image</option>
+          <option value="grid">Grid</option>
+        </select>
+        {quote && (
+          <span>
+            <span style={{ color: '#0f0' }}>{quote.bid ?? '-'}</span>
+            {' / '}
+            <span style={{ color: '#f44' }}>{quote.ask ?? '-'}</span>
+          </span>
+        )}
+        {mode.mode === 'auto' && aggregation && (
+          <span>nSigFigs {aggregation.nSigFigs ?? 'full'}</span>
+        )}
+        {(timeViewport.span !== null || timeViewport.until !== null) && (
+          <button onClick={resetZoom} title="Reset zoom and follow the latest data">
+            {timeViewport.until === null ? 'Zoomed' : 'Paused'}
+          </button>
+        )}
+        <button onClick={exportSvg}>SVG</button>
+        <button onClick={onRemove}>×</button>
+      </div>
+      {grid && <GridCanvas grid={grid} />}
+      {data && (
+        <img src={data.image} style={{
+          position: 'absolute',
+          inset: 0,
+          width: '100%',
+          height: '100%',
+          objectFit: 'contain'
+        }} />
+      )}
+      <div style={{
+        position: 'absolute',
+        inset: 0
+      }}
+        dangerouslySetInnerHTML={{ __html: data?.overlay ?? '' }}
+      />
+      {hover?.readout && (
+        <>
+          <div style={{ position: 'absolute', left: hover.left, top: 0, bottom: 0, borderLeft: '1px dashed rgba(255, 255, 255, 0.5)', pointerEvents: 'none' }} />
+          <div style={{ position: 'absolute', top: hover.top, left: 0, right: 0, borderTop: '1px dashed rgba(255, 255, 255, 0.5)', pointerEvents: 'none' }} />
+          <div style={{
+            position: 'absolute',
+            left: hover.left + 12,
+            top: hover.top + 12,
+            zIndex: 1,
+            padding: '4px 6px',
+            background: 'rgba(0, 0, 0, 0.8)',
+            color: '#fff',
+            font: '12px monospace',
+            whiteSpace: 'pre',
+            pointerEvents: 'none'
+          }}>
+            {[
+              formatTime(hover.readout.time, timeAxis.zone),
+              `Price ${hover.readout.price}`,
+              `Bid ${hover.readout.bidSize} (${hover.readout.bidOrders})`,
+              `Ask ${hover.readout.askSize} (${hover.readout.askOrders})`,
+              `Mid ${hover.readout.mid ?? '-'}`,
+            ].join('\n')}
+          </div>
+        </>
+      )}
+    </div>
+  );
+}
+
+function App() {
+  const [coins, setCoins] = useState<CoinInfo[]>([]);
+  const [coinInput, setCoinInput] = useState('');
+  const [markets, setMarkets] = useState<Market[]>([]);
+  const [stats, setStats] = useState<FrameStats | null>(null);
+
+  const refreshCoins = () => invoke<CoinInfo[]>('get_coins').then(setCoins);
+  const refreshStats = () => invoke<FrameStats>('get_frame_stats').then(setStats);
+
+  // 描画の統計を1秒ごとに更新
+  useEffect(() => {
+    refreshStats();
+    const timer = setInterval(refreshStats, 1000);
+    return () => clearInterval(timer);
+  }, []);
+
+  useEffect(() => {
+    refreshCoins();
+    invoke<Market[]>('list_markets')
+      .then(setMarkets)
+      .catch(err => console.error(err));
   }, []);
 
+  const switchCoin = (e: FormEvent) => {
+    e.preventDefault();
+    invoke('set_coin', { coin: coinInput })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const addCoin = () => {
+    invoke('add_coin', { coin: coinInput })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const removeCoin = (coin: string) => {
+    invoke('remove_coin', { coin })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeMode = (coin: string, mode: AggregationMode) => {
+    invoke('set_aggregation', { coin, mode })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeMetric = (coin: string, metric: HeatmapMetric) => {
+    invoke('set_heatmap_metric', { coin, metric })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeScale = (coin: string, scale: IntensityScale) => {
+    invoke('set_intensity_scale', { coin, scale })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeColors = (coin: string, colors: ColorMap) => {
+    invoke('set_color_map', { coin, colors })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeTimeAxis = (coin: string, axis: TimeAxis) => {
+    invoke('set_time_axis', { coin, axis })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeViewport = (coin: string, viewport: PriceViewport) => {
+    invoke('set_viewport', { coin, viewport })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeWindow = (coin: string, windowMs: number) => {
+    invoke('set_time_window', { coin, windowMs })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeStat = (coin: string, stat: BucketStat) => {
+    invoke('set_bucket_stat', { coin, stat })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeHistoryLimit = (coin: string, limitMb: number) => {
+    invoke('set_history_limit', { coin, limitMb })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const changeFrameRate = (fps: number) => {
+    invoke('set_frame_rate', { fps })
+      .then(refreshStats)
+      .catch(err => console.error(err));
+  };
+
+  const changeOutput = (coin: string, output: HeatmapOutput) => {
+    invoke('set_heatmap_output', { coin, output })
+      .then(refreshCoins)
+      .catch(err => console.error(err));
+  };
+
+  const symbolOf = (c: string) => markets.find(m => m.coin === c)?.symbol ?? c;
+  const columns = Math.max(1, Math.ceil(Math.sqrt(coins.length)));
+
   return (
     <div style={{
       width: '100vw',
@@ -26,16 +787,79 @@ function App() {
       padding: 0,
       overflow: 'hidden'
     }}>
+      <form onSubmit={switchCoin} style={{
+        display: 'flex',
+        gap: '8px',
+        padding: '4px 8px',
+        color: '#fff'
+      }}>
+        <input
+          list="markets"
+          value={coinInput}
+          onChange={e => setCoinInput(e.target.value)}
+          placeholder="BTC, ETH, HYPE/USDC..."
+        />
+        <datalist id="markets">
+          {markets.map(m => (
+            <option key={m.coin} value={m.symbol}>{m.kind} {m.coin}</option>
+          ))}
+        </datalist>
+        <button type="submit">Switch</button>
+        <button type="button" onClick={addCoin}>Add</button>
+        <select value={stats?.fps ?? 10} onChange={e => changeFrameRate(Number(e.target.value))}>
+          {FRAME_RATES.map(fps => (
+            <option key={fps} value={fps}>{fps} fps</option>
+          ))}
+        </select>
+        {stats && (
+          <span>
+            {stats.rendered} frames, {stats.dropped} dropped, {stats.coalesced} coalesced,
+            {' '}{stats.lastRenderMs.toFixed(1)} ms (max {stats.maxRenderMs.toFixed(1)} ms)
+          </span>
+        )}
+      </form>
       <div style={{
         flex: 1,
         width: '100%',
-        height: '100%',
-        display: 'flex',
-        justifyContent: 'center',
-        alignItems: 'center'
-      }}
-        dangerouslySetInnerHTML={{ __html: svgContent }}
-      />
+        minHeight: 0,
+        display: 'grid',
+        gridTemplateColumns: `repeat(${columns}, 1fr)`,
+        gridAutoRows: '1fr'
+      }}>
+        {coins.map(({ coin, event, gridEvent, mode, metric, scale, colors, timeAxis, viewport, timeViewport, windowMs, stat, historyLimitMb, historyBytes, output }) => (
+          <Heatmap
+            key={coin}
+            coin={coin}
+            eventName={event}
+            gridEventName={gridEvent}
+            title={symbolOf(coin)}
+            mode={mode}
+            metric={metric}
+            scale={scale}
+            colors={colors}
+            timeAxis={timeAxis}
+            viewport={viewport}
+            timeViewport={timeViewport}
+            windowMs={windowMs}
+            stat={stat}
+            historyLimitMb={historyLimitMb}
+            historyBytes={historyBytes}
+            output={output}
+            onModeChange={m => changeMode(coin, m)}
+            onMetricChange={m => changeMetric(coin, m)}
+            onScaleChange={s => changeScale(coin, s)}
+            onColorsChange={c => changeColors(coin, c)}
+            onTimeAxisChange={a => changeTimeAxis(coin, a)}
+            onViewportChange={v => changeViewport(coin, v)}
+            onViewChange={refreshCoins}
+            onWindowChange={w => changeWindow(coin, w)}
+            onStatChange={s => changeStat(coin, s)}
+            onHistoryLimitChange={l => changeHistoryLimit(coin, l)}
+            onOutputChange={o => changeOutput(coin, o)}
+            onRemove={() => removeCoin(coin)}
+          />
+        ))}
+      </div>
     </div>
   );
 }
@@ -0,0 +1,94 @@
+// 板の更新のバイナリ形式のデコーダ（形式は src-tauri/src/wire.rs を参照）
+import { Channel, invoke } from '@tauri-apps/api/core';
+
+export const WIRE_VERSION = 1;
+
+export type WireLevel = {
+  price: number;
+  size: number;
+  orders: number;
+};
+
+export type BookFrame = {
+  kind: 'snapshot' | 'delta';
+  coin: string;
+  nSigFigs: number | null;
+  mantissa: number | null;
+  time: number;
+  bids: WireLevel[];
+  asks: WireLevel[];
+};
+
+// 価格 → 価格レベル
+export type BookSide = Map<number, WireLevel>;
+
+export function decodeFrame(buffer: ArrayBuffer): BookFrame {
+  const view = new DataView(buffer);
+  if (view.getUint8(0) !== 0x48 || view.getUint8(1) !== 0x42) {
+    throw new Error('frame does not start with the magic bytes');
+  }
+  const version = view.getUint8(2);
+  if (version !== WIRE_VERSION) {
+    throw new Error(`unsupported wire version: ${version}`);
+  }
+  const kind = view.getUint8(3);
+  if (kind > 1) {
+    throw new Error(`unknown frame kind: ${kind}`);
+  }
+  const time = Number(view.getBigInt64(4, true));
+  const nSigFigs = view.getUint8(12) || null;
+  const mantissa = view.getUint8(13) || null;
+  const coinLength = view.getUint8(14);
+  const coin = new TextDecoder().decode(new Uint8Array(buffer, 15, coinLength));
+
+  let offset = 15 + coinLength;
+  const bidCount = view.getUint16(offset, true);
+  const askCount = view.getUint16(offset + 2, true);
+  offset += 4;
+  const levels = (count: number) => Array.from({ length: count }, () => {
+    const level = {
+      price: view.getFloat64(offset, true),
+      size: view.getFloat64(offset + 8, true),
+      orders: view.getUint32(offset + 16, true),
+    };
+    offset += 20;
+    return level;
+  });
+  const bids = levels(bidCount);
+  const asks = levels(askCount);
+
+  return { kind: kind === 0 ? 'snapshot' : 'delta', coin, nSigFigs, mantissa, time, bids, asks };
+}
+
+// スナップショットは置き換え、差分は上書き（size = 0 は削除）
+export function applyFrame(frame: BookFrame, bids: BookSide, asks: BookSide) {
+  if (frame.kind === 'snapshot') {
+    bids.clear();
+    asks.clear();
+  }
+  for (const [side, levels] of [[bids, frame.bids], [asks, frame.asks]] as const) {
+    for (const level of levels) {
+      if (level.size === 0) {
+        side.delete(level.price);
+      } else {
+        side.set(level.price, level);
+      }
+    }
+  }
+}
+
+// 銘柄の板をバイナリで購読し、更新のたびに最新の板を渡す。戻り値で購読をやめる
+export function streamBook(coin: string, onUpdate: (bids: BookSide, asks: BookSide, frame: BookFrame) => void) {
+  const bids: BookSide = new Map();
+  const asks: BookSide = new Map();
+  const channel = new Channel<ArrayBuffer>();
+  channel.onmessage = buffer => {
+    const frame = decodeFrame(buffer);
+    applyFrame(frame, bids, asks);
+    onUpdate(bids, asks, frame);
+  };
+  const id = invoke<number>('stream_book', { coin, channel });
+  return () => {
+    id.then(id => invoke('stop_book_stream', { id })).catch(err => console.error(err));
+  };
+}